@@ -0,0 +1,143 @@
+//! Acquisition backends a `DAQVTask` can run on
+
+#[cfg(feature = "nidaqmx")]
+mod nidaqmx;
+mod simulated;
+
+#[cfg(feature = "nidaqmx")]
+pub use self::nidaqmx::NIDAQmxBackend;
+pub use self::simulated::{SimulatedBackend, Signal};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::MeasurementMode;
+use crate::channel::{Accelerometer, Bridge, Current, Range, Rtd, StrainGage, Thermocouple};
+use crate::error::DAQmxError;
+use crate::trigger::Trigger;
+
+/// DAQmxErrorSamplesNotYetAvailable, a read timed out before the requested samples were acquired
+pub const ERR_SAMPLES_NOT_YET_AVAILABLE : i32 = -200284;
+/// DAQmxErrorSamplesNoLongerAvailable, the input buffer overflowed in a continuous acquisition
+pub const ERR_SAMPLES_NO_LONGER_AVAILABLE : i32 = -200279;
+/// Onboard device memory overflowed before samples reached the input buffer
+pub const ERR_DEVICE_MEMORY_OVERFLOW : i32 = -200361;
+/// DAQmxErrorInvalidAttributeValue
+pub const ERR_INVALID_ATTRIBUTE_VALUE : i32 = -200077;
+
+/// Whether the sample clock stops after a set number of samples
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SampleMode {
+    /// Acquire a set number of samples per channel, then stop
+    Finite,
+    /// Acquire until stopped, the sample count sizes the input buffer
+    Continuous
+}
+
+/// Identity and analog inputs of a device installed in the system
+#[derive(Clone, PartialEq, Debug, Default, Serialize)]
+pub struct DeviceInfo {
+    pub name : String,
+    /// Product type, e.g. NI 9205
+    pub product_type : String,
+    /// Serial number, 0 if the device has none
+    pub serial_number : u32,
+    /// Modules in the slots of a chassis
+    pub modules : Vec<String>,
+    /// Analog input physical channels
+    pub ai_channels : Vec<String>
+}
+
+/// Hardware (or simulated hardware) that analog input samples are acquired from
+///
+/// Calls mirror the NI-DAQmx task life cycle: channels are created, timing is configured,
+/// then the task is started, read and stopped for every batch (finite) or started once and
+/// read repeatedly (continuous). Failures are reported as
+/// `DAQmxError`s named after the DAQmx call the operation corresponds to, warnings are kept
+/// for [`take_warnings`](AcquisitionBackend::take_warnings).
+pub trait AcquisitionBackend : Send {
+    /// Names of the devices installed in the system, chassis and their modules included
+    fn device_names(&self) -> Result<Vec<String>, DAQmxError>;
+
+    /// Product type, serial number, modules and analog input channels of a device
+    fn device_info(&self, device : &str) -> Result<DeviceInfo, DAQmxError>;
+
+    /// Analog input voltage ranges supported by a device
+    fn voltage_ranges(&self, device : &str) -> Result<Vec<Range>, DAQmxError>;
+
+    /// Terminal configurations supported by a physical channel
+    fn terminal_configs(&self, physical : &str) -> Result<Vec<MeasurementMode>, DAQmxError>;
+
+    /// Create analog input voltage channels for a list of physical channels
+    fn create_voltage_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64) -> Result<(), DAQmxError>;
+
+    /// Create thermocouple channels for a list of physical channels, `min` and `max` are in the
+    /// thermocouple's units
+    fn create_thermocouple_channels(&mut self, channels : &str, min : f64, max : f64, thermocouple : &Thermocouple) -> Result<(), DAQmxError>;
+
+    /// Create RTD channels for a list of physical channels, `min` and `max` are in the RTD's units
+    fn create_rtd_channels(&mut self, channels : &str, min : f64, max : f64, rtd : &Rtd) -> Result<(), DAQmxError>;
+
+    /// Create current channels [A] for a list of physical channels
+    fn create_current_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64, current : &Current) -> Result<(), DAQmxError>;
+
+    /// Create strain gage channels [strain] for a list of physical channels
+    fn create_strain_channels(&mut self, channels : &str, min : f64, max : f64, strain : &StrainGage) -> Result<(), DAQmxError>;
+
+    /// Create bridge channels for a list of physical channels, `min` and `max` are in the bridge's units
+    fn create_bridge_channels(&mut self, channels : &str, min : f64, max : f64, bridge : &Bridge) -> Result<(), DAQmxError>;
+
+    /// Create IEPE accelerometer channels for a list of physical channels, `min` and `max` are
+    /// in the accelerometer's units
+    fn create_accelerometer_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64, accelerometer : &Accelerometer) -> Result<(), DAQmxError>;
+
+    /// Number of virtual channels in the task
+    fn num_channels(&self) -> Result<u32, DAQmxError>;
+
+    /// Names of the virtual channels in the task, physical channel names unless named otherwise
+    fn channel_names(&self) -> Result<Vec<String>, DAQmxError>;
+
+    /// Set sample rate [samples/sec], sample mode and number of samples per channel to acquire
+    /// (finite) or to buffer (continuous)
+    ///
+    /// Continuous acquisitions must not overwrite unread samples, so that falling behind is
+    /// reported by `read` as `ERR_SAMPLES_NO_LONGER_AVAILABLE` instead of losing data silently.
+    fn configure_timing(&mut self, sample_rate : f64, mode : SampleMode, sample_count : u64) -> Result<(), DAQmxError>;
+
+    /// Wait for `trigger` before acquiring the first sample, must precede `start`
+    fn configure_start_trigger(&mut self, trigger : &Trigger) -> Result<(), DAQmxError>;
+
+    /// Acquire into the buffer until `trigger`, keeping `pretrigger_samples` samples per channel
+    /// ahead of it, then stop once the sample count is reached. Finite acquisitions only, must
+    /// precede `start`
+    fn configure_reference_trigger(&mut self, trigger : &Trigger, pretrigger_samples : u64) -> Result<(), DAQmxError>;
+
+    /// Have the device timestamp the first sample of every acquisition, must precede `start`
+    fn enable_first_sample_timestamp(&mut self) -> Result<(), DAQmxError>;
+
+    /// Time the first sample was acquired at, if the device timestamps it
+    fn first_sample_timestamp(&self) -> Result<Option<DateTime<Utc>>, DAQmxError>;
+
+    /// Start acquisition
+    fn start(&mut self) -> Result<(), DAQmxError>;
+
+    /// Read samples interleaved by scan into `buffer`, returns number of samples read per channel.
+    /// If `timeout` [s] elapses first the read fails, the scans acquired by then are read and lost
+    fn read(&mut self, buffer : &mut [f64], timeout : f64) -> Result<usize, DAQmxError>;
+
+    /// Read like `read`, but return the scans read when `timeout` [s] elapses, possibly none,
+    /// instead of failing
+    fn read_partial(&mut self, buffer : &mut [f64], timeout : f64) -> Result<usize, DAQmxError>;
+
+    /// Total number of samples per channel acquired by the device since start, read or not
+    fn total_acquired(&self) -> Result<u64, DAQmxError>;
+
+    /// Stop acquisition
+    fn stop(&mut self) -> Result<(), DAQmxError>;
+
+    /// Warnings the driver returned since the last call, from calls that succeeded anyway
+    fn take_warnings(&mut self) -> Vec<DAQmxError> {
+        Vec::new()
+    }
+}
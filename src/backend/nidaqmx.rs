@@ -0,0 +1,537 @@
+use core::ffi::c_char;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use ni_daqmx_sys;
+
+use chrono::{DateTime, TimeDelta, TimeZone, Utc};
+
+use crate::MeasurementMode;
+use crate::channel::{CjcSource, ExcitationSource, Range, Rtd, RtdType, TemperatureUnits, Thermocouple, ThermocoupleType, Wiring};
+use crate::channel::{AccelerationUnits, Accelerometer, Bridge, BridgeConfig, BridgeUnits, Current, SensitivityUnits, ShuntLocation, StrainConfig, StrainGage};
+use crate::error::DAQmxError;
+use crate::trigger::{Edge, Trigger, WindowCondition};
+use super::{AcquisitionBackend, DeviceInfo, SampleMode, ERR_INVALID_ATTRIBUTE_VALUE, ERR_SAMPLES_NOT_YET_AVAILABLE};
+
+/// DAQmxErrorAttributeNotSupportedInTaskContext, the device doesn't have the property
+const ERR_ATTRIBUTE_NOT_SUPPORTED : i32 = -200452;
+
+/// Return DAQmx errors to the caller, keep warnings for `take_warnings` and carry on
+macro_rules! return_if_err {
+    ($backend:expr,$prefix:expr,$err:expr) => {
+        let err = $err;
+        if err < 0 {
+            return Err(from_status($prefix, err));
+        } else if err > 0 {
+            $backend.warnings.borrow_mut().push(from_status($prefix, err));
+        }
+    };
+}
+
+/// Build an error from a status code just returned by `function`, looking up the driver's message
+///
+/// Errors carry the extended error information of the failed call, which names the
+/// offending channel or property; warnings carry the generic description of the code.
+fn from_status(function : &'static str, code : i32) -> DAQmxError {
+    let message = if code < 0 { extended_error_info() } else { error_string(code) };
+    DAQmxError::new(function, code, message)
+}
+
+/// Convert a driver-filled, nul-terminated buffer to a string
+fn buffer_to_string(buffer : &[u8]) -> String {
+    match CStr::from_bytes_until_nul(buffer) {
+        Ok(s) => s.to_string_lossy().into_owned(),
+        Err(_) => String::from_utf8_lossy(buffer).into_owned()
+    }
+}
+
+/// Extended information about the last error on this thread
+fn extended_error_info() -> String {
+    unsafe {
+        // Called with an empty buffer the function returns the required size
+        let size = ni_daqmx_sys::DAQmxGetExtendedErrorInfo(std::ptr::null_mut(), 0);
+        if size <= 0 {
+            return String::new();
+        }
+        let mut buffer = vec![0u8; size as usize];
+        ni_daqmx_sys::DAQmxGetExtendedErrorInfo(buffer.as_mut_ptr() as *mut c_char, size as u32);
+        buffer_to_string(&buffer)
+    }
+}
+
+/// Generic description of a status code
+fn error_string(code : i32) -> String {
+    unsafe {
+        let size = ni_daqmx_sys::DAQmxGetErrorString(code, std::ptr::null_mut(), 0);
+        if size <= 0 {
+            return String::new();
+        }
+        let mut buffer = vec![0u8; size as usize];
+        ni_daqmx_sys::DAQmxGetErrorString(code, buffer.as_mut_ptr() as *mut c_char, size as u32);
+        buffer_to_string(&buffer)
+    }
+}
+
+/// Backend driving a real device through the NI-DAQmx driver
+#[derive(Debug)]
+pub struct NIDAQmxBackend {
+    task_handle : ni_daqmx_sys::TaskHandle,
+    first_sample_timestamp : bool,
+    /// Warnings returned by calls that succeeded, until taken
+    warnings : RefCell<Vec<DAQmxError>>
+}
+
+// DAQmx task handles may be used from any thread, the driver serializes access internally
+unsafe impl Send for NIDAQmxBackend {}
+
+impl NIDAQmxBackend {
+    /// Create an empty measurement task
+    pub fn new() -> Result<NIDAQmxBackend, DAQmxError> {
+        let mut backend = NIDAQmxBackend {
+            task_handle : std::ptr::null_mut(),
+            first_sample_timestamp : false,
+            warnings : RefCell::new(Vec::new())
+        };
+        unsafe {
+            return_if_err!(backend, "DAQmxCreateTask", ni_daqmx_sys::DAQmxCreateTask(std::ptr::null(), &mut backend.task_handle));
+        }
+        Ok(backend)
+    }
+
+    /// Read a full buffer, returning the status of the read and the samples per channel read,
+    /// which DAQmx also reports for a read that timed out
+    fn read_analog(&self, buffer : &mut [f64], timeout : f64) -> Result<(i32, usize), DAQmxError> {
+        // Ask for a full buffer, DAQmx_Val_Auto would return whatever is available in continuous mode
+        let samples_per_channel = buffer.len()/(self.num_channels()? as usize);
+        let mut read : i32 = 0;
+        let status = unsafe {
+            ni_daqmx_sys::DAQmxReadAnalogF64(
+                self.task_handle,
+                samples_per_channel as i32,
+                timeout,
+                ni_daqmx_sys::DAQmx_Val_GroupByScanNumber as u32,
+                buffer.as_mut_ptr(),
+                buffer.len() as u32,
+                &mut read, std::ptr::null_mut())
+        };
+        Ok((status, usize::try_from(read).unwrap_or(0)))
+    }
+
+    /// Read a string property with a DAQmx getter taking a buffer and its size
+    fn get_string(&self, function : &'static str, get : impl Fn(*mut c_char, u32) -> i32) -> Result<String, DAQmxError> {
+        // Called with an empty buffer the function returns the required size
+        let size = get(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(from_status(function, size));
+        } else if size == 0 {
+            return Ok(String::new());
+        }
+        let mut buffer = vec![0u8; size as usize];
+        return_if_err!(self, function, get(buffer.as_mut_ptr() as *mut c_char, size as u32));
+        Ok(CStr::from_bytes_until_nul(&buffer).map(|s| s.to_string_lossy().into_owned()).unwrap_or_default())
+    }
+}
+
+fn terminal_config(mode : MeasurementMode) -> i32 {
+    match mode {
+        MeasurementMode::RSE => ni_daqmx_sys::DAQmx_Val_RSE,
+        MeasurementMode::NRSE => ni_daqmx_sys::DAQmx_Val_NRSE,
+        MeasurementMode::DIFF => ni_daqmx_sys::DAQmx_Val_Diff,
+        MeasurementMode::PSEUDODIFF => ni_daqmx_sys::DAQmx_Val_PseudoDiff,
+    }
+}
+
+fn excitation_source(source : ExcitationSource) -> i32 {
+    match source {
+        ExcitationSource::Internal => ni_daqmx_sys::DAQmx_Val_Internal,
+        ExcitationSource::External => ni_daqmx_sys::DAQmx_Val_External,
+        ExcitationSource::None => ni_daqmx_sys::DAQmx_Val_None,
+    }
+}
+
+fn digital_edge(edge : Edge) -> i32 {
+    match edge {
+        Edge::Rising => ni_daqmx_sys::DAQmx_Val_Rising,
+        Edge::Falling => ni_daqmx_sys::DAQmx_Val_Falling,
+    }
+}
+
+fn analog_slope(edge : Edge) -> i32 {
+    match edge {
+        Edge::Rising => ni_daqmx_sys::DAQmx_Val_RisingSlope,
+        Edge::Falling => ni_daqmx_sys::DAQmx_Val_FallingSlope,
+    }
+}
+
+fn window_condition(when : WindowCondition) -> i32 {
+    match when {
+        WindowCondition::Entering => ni_daqmx_sys::DAQmx_Val_EnteringWin,
+        WindowCondition::Leaving => ni_daqmx_sys::DAQmx_Val_LeavingWin,
+    }
+}
+
+fn temperature_units(units : TemperatureUnits) -> i32 {
+    match units {
+        TemperatureUnits::DegC => ni_daqmx_sys::DAQmx_Val_DegC,
+        TemperatureUnits::DegF => ni_daqmx_sys::DAQmx_Val_DegF,
+        TemperatureUnits::Kelvin => ni_daqmx_sys::DAQmx_Val_Kelvins,
+        TemperatureUnits::DegR => ni_daqmx_sys::DAQmx_Val_DegR,
+    }
+}
+
+/// Split a comma-separated list returned by the driver
+fn split_list(list : &str) -> Vec<String> {
+    list.split(',').map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect()
+}
+
+/// Convert LabWindows/CVI absolute time (seconds since 1904-01-01 UTC plus a 64-bit binary
+/// fraction of a second) to a chrono timestamp
+fn from_cvi_absolute_time(time : ni_daqmx_sys::CVIAbsoluteTime) -> DateTime<Utc> {
+    let (seconds, fraction) = unsafe { (time.cviTime.msb, time.cviTime.lsb) };
+    let nanoseconds = ((fraction as u128*1_000_000_000) >> 64) as i64;
+    let epoch = Utc.with_ymd_and_hms(1904, 1, 1, 0, 0, 0).unwrap();
+    epoch + TimeDelta::seconds(seconds) + TimeDelta::nanoseconds(nanoseconds)
+}
+
+impl AcquisitionBackend for NIDAQmxBackend {
+    fn device_names(&self) -> Result<Vec<String>, DAQmxError> {
+        let names = self.get_string("DAQmxGetSysDevNames", |data, size| unsafe { ni_daqmx_sys::DAQmxGetSysDevNames(data, size) })?;
+        Ok(split_list(&names))
+    }
+
+    fn device_info(&self, device : &str) -> Result<DeviceInfo, DAQmxError> {
+        let name = CString::new(device).expect("CString::new failed");
+        let device_ptr = name.as_ptr();
+        let product_type = self.get_string("DAQmxGetDevProductType", |data, size| unsafe { ni_daqmx_sys::DAQmxGetDevProductType(device_ptr, data, size) })?;
+        let mut serial_number : u32 = 0;
+        unsafe {
+            return_if_err!(self, "DAQmxGetDevSerialNum", ni_daqmx_sys::DAQmxGetDevSerialNum(device_ptr, &mut serial_number));
+        }
+        // Only chassis have modules
+        let modules = match self.get_string("DAQmxGetDevChassisModuleDevNames", |data, size| unsafe { ni_daqmx_sys::DAQmxGetDevChassisModuleDevNames(device_ptr, data, size) }) {
+            Err(err) if err.code() == ERR_ATTRIBUTE_NOT_SUPPORTED => String::new(),
+            result => result?,
+        };
+        let ai_channels = self.get_string("DAQmxGetDevAIPhysicalChans", |data, size| unsafe { ni_daqmx_sys::DAQmxGetDevAIPhysicalChans(device_ptr, data, size) })?;
+        Ok(DeviceInfo {
+            name : String::from(device),
+            product_type : product_type,
+            serial_number : serial_number,
+            modules : split_list(&modules),
+            ai_channels : split_list(&ai_channels)
+        })
+    }
+
+    fn voltage_ranges(&self, device : &str) -> Result<Vec<Range>, DAQmxError> {
+        let device = CString::new(device).expect("CString::new failed");
+        let mut ranges = Vec::<ni_daqmx_sys::float64>::new();
+        unsafe {
+            // Called with an empty array the function returns the required number of elements
+            let size = ni_daqmx_sys::DAQmxGetDevAIVoltageRngs(device.as_ptr(), std::ptr::null_mut(), 0);
+            if size < 0 {
+                return Err(from_status("DAQmxGetDevAIVoltageRngs", size));
+            } else if size == 0 {
+                return Ok(Vec::new());
+            }
+            ranges.resize(size as usize, 0.0);
+            return_if_err!(self, "DAQmxGetDevAIVoltageRngs", ni_daqmx_sys::DAQmxGetDevAIVoltageRngs(device.as_ptr(), ranges.as_mut_ptr(), size as u32));
+        }
+        // Ranges come as flattened min, max pairs
+        Ok(ranges.chunks_exact(2).map(|pair| Range { min : pair[0], max : pair[1] }).collect())
+    }
+
+    fn terminal_configs(&self, physical : &str) -> Result<Vec<MeasurementMode>, DAQmxError> {
+        let physical = CString::new(physical).expect("CString::new failed");
+        let mut bits : i32 = 0;
+        unsafe {
+            return_if_err!(self, "DAQmxGetPhysicalChanAITermCfgs", ni_daqmx_sys::DAQmxGetPhysicalChanAITermCfgs(physical.as_ptr(), &mut bits));
+        }
+        let modes = [
+            (ni_daqmx_sys::DAQmx_Val_Bit_TermCfg_RSE, MeasurementMode::RSE),
+            (ni_daqmx_sys::DAQmx_Val_Bit_TermCfg_NRSE, MeasurementMode::NRSE),
+            (ni_daqmx_sys::DAQmx_Val_Bit_TermCfg_Diff, MeasurementMode::DIFF),
+            (ni_daqmx_sys::DAQmx_Val_Bit_TermCfg_PseudoDIFF, MeasurementMode::PSEUDODIFF)
+        ];
+        Ok(modes.iter().filter(|(bit, _)| bits & bit != 0).map(|&(_, mode)| mode).collect())
+    }
+
+    fn create_voltage_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64) -> Result<(), DAQmxError> {
+        // Translate mode options
+        let mode = terminal_config(mode);
+
+        let ch_name = CString::new(channels).expect("CString::new failed");
+        let ch_name_ptr: *const c_char = ch_name.as_ptr();
+
+        unsafe {
+            // Create channels and set measurement mode
+            return_if_err!(self, "DAQmxCreateAIVoltageChan", ni_daqmx_sys::DAQmxCreateAIVoltageChan(self.task_handle, ch_name_ptr, std::ptr::null(), mode, min, max, ni_daqmx_sys::DAQmx_Val_Volts, std::ptr::null()));
+        }
+        Ok(())
+    }
+
+    fn create_thermocouple_channels(&mut self, channels : &str, min : f64, max : f64, thermocouple : &Thermocouple) -> Result<(), DAQmxError> {
+        let thermocouple_type = match thermocouple.thermocouple_type {
+            ThermocoupleType::B => ni_daqmx_sys::DAQmx_Val_B_Type_TC,
+            ThermocoupleType::E => ni_daqmx_sys::DAQmx_Val_E_Type_TC,
+            ThermocoupleType::J => ni_daqmx_sys::DAQmx_Val_J_Type_TC,
+            ThermocoupleType::K => ni_daqmx_sys::DAQmx_Val_K_Type_TC,
+            ThermocoupleType::N => ni_daqmx_sys::DAQmx_Val_N_Type_TC,
+            ThermocoupleType::R => ni_daqmx_sys::DAQmx_Val_R_Type_TC,
+            ThermocoupleType::S => ni_daqmx_sys::DAQmx_Val_S_Type_TC,
+            ThermocoupleType::T => ni_daqmx_sys::DAQmx_Val_T_Type_TC,
+        };
+        let cjc_source = match thermocouple.cjc_source {
+            CjcSource::BuiltIn => ni_daqmx_sys::DAQmx_Val_BuiltIn,
+            CjcSource::Constant => ni_daqmx_sys::DAQmx_Val_ConstVal,
+            CjcSource::Channel => ni_daqmx_sys::DAQmx_Val_Chan,
+        };
+
+        let ch_name = CString::new(channels).expect("CString::new failed");
+        // The CJC channel is only read by the driver if the source is a channel
+        let cjc_channel = CString::new(thermocouple.cjc_channel.as_deref().unwrap_or("")).expect("CString::new failed");
+
+        unsafe {
+            return_if_err!(self, "DAQmxCreateAIThrmcplChan", ni_daqmx_sys::DAQmxCreateAIThrmcplChan(self.task_handle, ch_name.as_ptr(), std::ptr::null(), min, max, temperature_units(thermocouple.units), thermocouple_type, cjc_source, thermocouple.cjc_value, cjc_channel.as_ptr()));
+        }
+        Ok(())
+    }
+
+    fn create_rtd_channels(&mut self, channels : &str, min : f64, max : f64, rtd : &Rtd) -> Result<(), DAQmxError> {
+        let rtd_type = match rtd.rtd_type {
+            RtdType::Pt3750 => ni_daqmx_sys::DAQmx_Val_Pt3750,
+            RtdType::Pt3851 => ni_daqmx_sys::DAQmx_Val_Pt3851,
+            RtdType::Pt3911 => ni_daqmx_sys::DAQmx_Val_Pt3911,
+            RtdType::Pt3916 => ni_daqmx_sys::DAQmx_Val_Pt3916,
+            RtdType::Pt3920 => ni_daqmx_sys::DAQmx_Val_Pt3920,
+            RtdType::Pt3928 => ni_daqmx_sys::DAQmx_Val_Pt3928,
+        };
+        let wiring = match rtd.wiring {
+            Wiring::TwoWire => ni_daqmx_sys::DAQmx_Val_2Wire,
+            Wiring::ThreeWire => ni_daqmx_sys::DAQmx_Val_3Wire,
+            Wiring::FourWire => ni_daqmx_sys::DAQmx_Val_4Wire,
+        };
+
+        let ch_name = CString::new(channels).expect("CString::new failed");
+
+        unsafe {
+            return_if_err!(self, "DAQmxCreateAIRTDChan", ni_daqmx_sys::DAQmxCreateAIRTDChan(self.task_handle, ch_name.as_ptr(), std::ptr::null(), min, max, temperature_units(rtd.units), rtd_type, wiring, excitation_source(rtd.excitation), rtd.current, rtd.r0));
+        }
+        Ok(())
+    }
+
+    fn create_current_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64, current : &Current) -> Result<(), DAQmxError> {
+        let shunt = match current.shunt {
+            ShuntLocation::Default => ni_daqmx_sys::DAQmx_Val_Default,
+            ShuntLocation::Internal => ni_daqmx_sys::DAQmx_Val_Internal,
+            ShuntLocation::External => ni_daqmx_sys::DAQmx_Val_External,
+        };
+
+        let ch_name = CString::new(channels).expect("CString::new failed");
+
+        unsafe {
+            return_if_err!(self, "DAQmxCreateAICurrentChan", ni_daqmx_sys::DAQmxCreateAICurrentChan(self.task_handle, ch_name.as_ptr(), std::ptr::null(), terminal_config(mode), min, max, ni_daqmx_sys::DAQmx_Val_Amps, shunt, current.shunt_resistance, std::ptr::null()));
+        }
+        Ok(())
+    }
+
+    fn create_strain_channels(&mut self, channels : &str, min : f64, max : f64, strain : &StrainGage) -> Result<(), DAQmxError> {
+        let configuration = match strain.configuration {
+            StrainConfig::FullBridgeI => ni_daqmx_sys::DAQmx_Val_FullBridgeI,
+            StrainConfig::FullBridgeII => ni_daqmx_sys::DAQmx_Val_FullBridgeII,
+            StrainConfig::FullBridgeIII => ni_daqmx_sys::DAQmx_Val_FullBridgeIII,
+            StrainConfig::HalfBridgeI => ni_daqmx_sys::DAQmx_Val_HalfBridgeI,
+            StrainConfig::HalfBridgeII => ni_daqmx_sys::DAQmx_Val_HalfBridgeII,
+            StrainConfig::QuarterBridgeI => ni_daqmx_sys::DAQmx_Val_QuarterBridgeI,
+            StrainConfig::QuarterBridgeII => ni_daqmx_sys::DAQmx_Val_QuarterBridgeII,
+        };
+
+        let ch_name = CString::new(channels).expect("CString::new failed");
+
+        unsafe {
+            return_if_err!(self, "DAQmxCreateAIStrainGageChan", ni_daqmx_sys::DAQmxCreateAIStrainGageChan(self.task_handle, ch_name.as_ptr(), std::ptr::null(), min, max, ni_daqmx_sys::DAQmx_Val_Strain, configuration, excitation_source(strain.excitation), strain.voltage, strain.gage_factor, strain.initial_bridge_voltage, strain.nominal_resistance, strain.poisson_ratio, strain.lead_resistance, std::ptr::null()));
+        }
+        Ok(())
+    }
+
+    fn create_bridge_channels(&mut self, channels : &str, min : f64, max : f64, bridge : &Bridge) -> Result<(), DAQmxError> {
+        let configuration = match bridge.configuration {
+            BridgeConfig::Full => ni_daqmx_sys::DAQmx_Val_FullBridge,
+            BridgeConfig::Half => ni_daqmx_sys::DAQmx_Val_HalfBridge,
+            BridgeConfig::Quarter => ni_daqmx_sys::DAQmx_Val_QuarterBridge,
+        };
+        let units = match bridge.units {
+            BridgeUnits::MillivoltsPerVolt => ni_daqmx_sys::DAQmx_Val_mVoltsPerVolt,
+            BridgeUnits::VoltsPerVolt => ni_daqmx_sys::DAQmx_Val_VoltsPerVolt,
+        };
+
+        let ch_name = CString::new(channels).expect("CString::new failed");
+
+        unsafe {
+            return_if_err!(self, "DAQmxCreateAIBridgeChan", ni_daqmx_sys::DAQmxCreateAIBridgeChan(self.task_handle, ch_name.as_ptr(), std::ptr::null(), min, max, units, configuration, excitation_source(bridge.excitation), bridge.voltage, bridge.nominal_resistance, std::ptr::null()));
+        }
+        Ok(())
+    }
+
+    fn create_accelerometer_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64, accelerometer : &Accelerometer) -> Result<(), DAQmxError> {
+        let units = match accelerometer.units {
+            AccelerationUnits::G => ni_daqmx_sys::DAQmx_Val_AccelUnit_g,
+            AccelerationUnits::MetersPerSecondSquared => ni_daqmx_sys::DAQmx_Val_MetersPerSecondSquared,
+            AccelerationUnits::InchesPerSecondSquared => ni_daqmx_sys::DAQmx_Val_InchesPerSecondSquared,
+        };
+        let sensitivity_units = match accelerometer.sensitivity_units {
+            SensitivityUnits::MillivoltsPerG => ni_daqmx_sys::DAQmx_Val_mVoltsPerG,
+            SensitivityUnits::VoltsPerG => ni_daqmx_sys::DAQmx_Val_VoltsPerG,
+        };
+
+        let ch_name = CString::new(channels).expect("CString::new failed");
+
+        unsafe {
+            return_if_err!(self, "DAQmxCreateAIAccelChan", ni_daqmx_sys::DAQmxCreateAIAccelChan(self.task_handle, ch_name.as_ptr(), std::ptr::null(), terminal_config(mode), min, max, units, accelerometer.sensitivity, sensitivity_units, excitation_source(accelerometer.excitation), accelerometer.current, std::ptr::null()));
+        }
+        Ok(())
+    }
+
+    fn num_channels(&self) -> Result<u32, DAQmxError> {
+        let mut channels : u32 = 0;
+        unsafe {
+            return_if_err!(self, "DAQmxGetTaskNumChans", ni_daqmx_sys::DAQmxGetTaskNumChans(self.task_handle, &mut channels));
+        }
+        Ok(channels)
+    }
+
+    fn channel_names(&self) -> Result<Vec<String>, DAQmxError> {
+        let names = self.get_string("DAQmxGetTaskChannels", |data, size| unsafe { ni_daqmx_sys::DAQmxGetTaskChannels(self.task_handle, data, size) })?;
+        Ok(split_list(&names))
+    }
+
+    fn configure_timing(&mut self, sample_rate : f64, mode : SampleMode, sample_count : u64) -> Result<(), DAQmxError> {
+        let sample_mode = match mode {
+            SampleMode::Finite => ni_daqmx_sys::DAQmx_Val_FiniteSamps,
+            SampleMode::Continuous => ni_daqmx_sys::DAQmx_Val_ContSamps,
+        };
+        unsafe {
+            // Set sample rate, sample count, trigger mode
+            return_if_err!(self, "DAQmxCfgSampClkTiming", ni_daqmx_sys::DAQmxCfgSampClkTiming(self.task_handle, std::ptr::null(), sample_rate, ni_daqmx_sys::DAQmx_Val_Rising, sample_mode, sample_count));
+            // Fail reads on buffer overflow instead of silently overwriting unread samples
+            return_if_err!(self, "DAQmxSetReadOverWrite", ni_daqmx_sys::DAQmxSetReadOverWrite(self.task_handle, ni_daqmx_sys::DAQmx_Val_DoNotOverwriteUnreadSamps));
+        }
+        Ok(())
+    }
+
+    fn configure_start_trigger(&mut self, trigger : &Trigger) -> Result<(), DAQmxError> {
+        let source = CString::new(trigger.source()).expect("CString::new failed");
+        unsafe {
+            match *trigger {
+                Trigger::DigitalEdge { edge, .. } => {
+                    return_if_err!(self, "DAQmxCfgDigEdgeStartTrig", ni_daqmx_sys::DAQmxCfgDigEdgeStartTrig(self.task_handle, source.as_ptr(), digital_edge(edge)));
+                }
+                Trigger::AnalogEdge { edge, level, .. } => {
+                    return_if_err!(self, "DAQmxCfgAnlgEdgeStartTrig", ni_daqmx_sys::DAQmxCfgAnlgEdgeStartTrig(self.task_handle, source.as_ptr(), analog_slope(edge), level));
+                }
+                Trigger::AnalogWindow { when, bottom, top, .. } => {
+                    return_if_err!(self, "DAQmxCfgAnlgWindowStartTrig", ni_daqmx_sys::DAQmxCfgAnlgWindowStartTrig(self.task_handle, source.as_ptr(), window_condition(when), top, bottom));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn configure_reference_trigger(&mut self, trigger : &Trigger, pretrigger_samples : u64) -> Result<(), DAQmxError> {
+        let source = CString::new(trigger.source()).expect("CString::new failed");
+        // The driver takes a 32-bit count
+        let pretrigger_samples = u32::try_from(pretrigger_samples).map_err(|_| {
+            let function = match trigger {
+                Trigger::DigitalEdge { .. } => "DAQmxCfgDigEdgeRefTrig",
+                Trigger::AnalogEdge { .. } => "DAQmxCfgAnlgEdgeRefTrig",
+                Trigger::AnalogWindow { .. } => "DAQmxCfgAnlgWindowRefTrig",
+            };
+            DAQmxError::new(function, ERR_INVALID_ATTRIBUTE_VALUE, format!("{} pretrigger samples don't fit in a 32-bit unsigned integer.", pretrigger_samples))
+        })?;
+        unsafe {
+            match *trigger {
+                Trigger::DigitalEdge { edge, .. } => {
+                    return_if_err!(self, "DAQmxCfgDigEdgeRefTrig", ni_daqmx_sys::DAQmxCfgDigEdgeRefTrig(self.task_handle, source.as_ptr(), digital_edge(edge), pretrigger_samples));
+                }
+                Trigger::AnalogEdge { edge, level, .. } => {
+                    return_if_err!(self, "DAQmxCfgAnlgEdgeRefTrig", ni_daqmx_sys::DAQmxCfgAnlgEdgeRefTrig(self.task_handle, source.as_ptr(), analog_slope(edge), level, pretrigger_samples));
+                }
+                Trigger::AnalogWindow { when, bottom, top, .. } => {
+                    return_if_err!(self, "DAQmxCfgAnlgWindowRefTrig", ni_daqmx_sys::DAQmxCfgAnlgWindowRefTrig(self.task_handle, source.as_ptr(), window_condition(when), top, bottom, pretrigger_samples));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn enable_first_sample_timestamp(&mut self) -> Result<(), DAQmxError> {
+        unsafe {
+            return_if_err!(self, "DAQmxSetFirstSampTimestampEnable", ni_daqmx_sys::DAQmxSetFirstSampTimestampEnable(self.task_handle, 1));
+        }
+        self.first_sample_timestamp = true;
+        Ok(())
+    }
+
+    fn first_sample_timestamp(&self) -> Result<Option<DateTime<Utc>>, DAQmxError> {
+        if !self.first_sample_timestamp {
+            return Ok(None);
+        }
+        let mut time : ni_daqmx_sys::CVIAbsoluteTime = unsafe { std::mem::zeroed() };
+        unsafe {
+            return_if_err!(self, "DAQmxGetFirstSampTimestampVal", ni_daqmx_sys::DAQmxGetFirstSampTimestampVal(self.task_handle, &mut time));
+        }
+        Ok(Some(from_cvi_absolute_time(time)))
+    }
+
+    fn start(&mut self) -> Result<(), DAQmxError> {
+        unsafe {
+            return_if_err!(self, "DAQmxStartTask", ni_daqmx_sys::DAQmxStartTask(self.task_handle));
+        }
+        Ok(())
+    }
+
+    fn read(&mut self, buffer : &mut [f64], timeout : f64) -> Result<usize, DAQmxError> {
+        let (status, read) = self.read_analog(buffer, timeout)?;
+        return_if_err!(self, "DAQmxReadAnalogF64", status);
+        Ok(read)
+    }
+
+    fn read_partial(&mut self, buffer : &mut [f64], timeout : f64) -> Result<usize, DAQmxError> {
+        let (status, read) = self.read_analog(buffer, timeout)?;
+        if status != ERR_SAMPLES_NOT_YET_AVAILABLE {
+            return_if_err!(self, "DAQmxReadAnalogF64", status);
+        }
+        Ok(read)
+    }
+
+    fn total_acquired(&self) -> Result<u64, DAQmxError> {
+        let mut acquired : u64 = 0;
+        unsafe {
+            return_if_err!(self, "DAQmxGetReadTotalSampPerChanAcquired", ni_daqmx_sys::DAQmxGetReadTotalSampPerChanAcquired(self.task_handle, &mut acquired));
+        }
+        Ok(acquired)
+    }
+
+    fn stop(&mut self) -> Result<(), DAQmxError> {
+        unsafe {
+            return_if_err!(self, "DAQmxStopTask", ni_daqmx_sys::DAQmxStopTask(self.task_handle));
+        }
+        Ok(())
+    }
+
+    fn take_warnings(&mut self) -> Vec<DAQmxError> {
+        self.warnings.take()
+    }
+}
+
+impl Drop for NIDAQmxBackend {
+    /// Clean up
+    fn drop(&mut self) {
+
+        if !self.task_handle.is_null() {
+            // Nobody is left to report failures to
+            unsafe {
+                ni_daqmx_sys::DAQmxStopTask(self.task_handle);
+                ni_daqmx_sys::DAQmxClearTask(self.task_handle);
+            }
+        }
+    }
+}
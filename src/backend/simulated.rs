@@ -0,0 +1,607 @@
+use std::cmp::Ordering;
+use std::f64::consts::PI;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::MeasurementMode;
+use crate::channel::{Accelerometer, Bridge, CjcSource, Current, Range, Rtd, ShuntLocation, StrainGage, TemperatureUnits, Thermocouple};
+use crate::channel::expand_physical_channels;
+use crate::error::DAQmxError;
+use crate::trigger::{Edge, Trigger, WindowCondition};
+use super::{AcquisitionBackend, DeviceInfo, SampleMode, ERR_INVALID_ATTRIBUTE_VALUE, ERR_SAMPLES_NOT_YET_AVAILABLE, ERR_SAMPLES_NO_LONGER_AVAILABLE};
+/// DAQmxErrorPhysicalChanDoesNotExist
+const ERR_PHYSICAL_CHAN_DOES_NOT_EXIST : i32 = -200170;
+
+/// Amplitude of mains hum picked up by single-ended channels [V]
+const HUM_AMPLITUDE : f64 = 0.005;
+/// Mains frequency [Hz]
+const HUM_FREQUENCY : f64 = 50.0;
+
+/// Temperature the simulated temperature channels vary around [°C]
+const AMBIENT_TEMPERATURE : f64 = 22.0;
+/// Temperature change per volt of the signal a temperature channel is assigned [°C]
+const TEMPERATURE_SWING : f64 = 2.0;
+/// Current channels simulate a 4-20 mA transmitter at mid-scale, varying by this much per volt of signal [A]
+const CURRENT_SWING : f64 = 0.008;
+/// Strain per volt of signal [strain]
+const STRAIN_SWING : f64 = 0.0005;
+/// Bridge output per volt of signal [mV/V]
+const BRIDGE_SWING : f64 = 0.5;
+
+/// How far ahead of the start a trigger condition is looked for, a trigger that doesn't occur
+/// within this time never fires [s]
+const TRIGGER_SEARCH_TIME : f64 = 60.0;
+
+/// Input ranges of the simulated device, those of an NI 9205 [V]
+const VOLTAGE_RANGES : [(f64, f64); 4] = [(-0.2, 0.2), (-1.0, 1.0), (-5.0, 5.0), (-10.0, 10.0)];
+
+/// DAQmxErrorInvalidDeviceID
+const ERR_INVALID_DEVICE_ID : i32 = -200220;
+
+/// Device installed in the simulated system
+struct SimulatedDevice {
+    name : &'static str,
+    product_type : &'static str,
+    serial_number : u32,
+    modules : &'static [&'static str],
+    /// Number of analog inputs, named ai0, ai1, ...
+    ai_channels : usize,
+    terminals : &'static [MeasurementMode],
+    ranges : &'static [(f64, f64)]
+}
+
+/// A cDAQ chassis with a multifunction and a simultaneous-sampling module. Channels of other
+/// devices can be used too, they support every terminal configuration and the ranges of the
+/// multifunction module
+const DEVICES : [SimulatedDevice; 3] = [
+    SimulatedDevice {
+        name : "cDAQSim",
+        product_type : "cDAQ-9189",
+        serial_number : 0x1F00001,
+        modules : &["cDAQSimMod1", "cDAQSimMod2"],
+        ai_channels : 0,
+        terminals : &[],
+        ranges : &[]
+    },
+    SimulatedDevice {
+        name : "cDAQSimMod1",
+        product_type : "NI 9205",
+        serial_number : 0x1F00002,
+        modules : &[],
+        ai_channels : 16,
+        terminals : &[MeasurementMode::RSE, MeasurementMode::NRSE, MeasurementMode::DIFF],
+        ranges : &VOLTAGE_RANGES
+    },
+    SimulatedDevice {
+        name : "cDAQSimMod2",
+        product_type : "NI 9215",
+        serial_number : 0x1F00003,
+        modules : &[],
+        ai_channels : 4,
+        terminals : &[MeasurementMode::DIFF],
+        ranges : &[(-10.0, 10.0)]
+    }
+];
+
+/// Simulated device a device or physical channel name refers to
+fn find_device(name : &str) -> Option<&'static SimulatedDevice> {
+    let device = name.trim().trim_start_matches('/').split('/').next().unwrap_or("");
+    DEVICES.iter().find(|known| known.name == device)
+}
+
+/// Error the driver would report for the same condition
+fn error(function : &'static str, code : i32, message : &str) -> DAQmxError {
+    DAQmxError::new(function, code, String::from(message))
+}
+
+/// DAQmx function the NI backend configures a start trigger of this kind with
+fn start_trigger_function(trigger : &Trigger) -> &'static str {
+    match trigger {
+        Trigger::DigitalEdge { .. } => "DAQmxCfgDigEdgeStartTrig",
+        Trigger::AnalogEdge { .. } => "DAQmxCfgAnlgEdgeStartTrig",
+        Trigger::AnalogWindow { .. } => "DAQmxCfgAnlgWindowStartTrig",
+    }
+}
+
+/// DAQmx function the NI backend configures a reference trigger of this kind with
+fn reference_trigger_function(trigger : &Trigger) -> &'static str {
+    match trigger {
+        Trigger::DigitalEdge { .. } => "DAQmxCfgDigEdgeRefTrig",
+        Trigger::AnalogEdge { .. } => "DAQmxCfgAnlgEdgeRefTrig",
+        Trigger::AnalogWindow { .. } => "DAQmxCfgAnlgWindowRefTrig",
+    }
+}
+
+/// Waveform generated on a simulated channel
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Signal {
+    /// 1 Hz sine wave, 1 V amplitude, phase shifted by channel index
+    Sine,
+    /// White noise, 0.1 V standard deviation
+    Noise,
+    /// Square wave stepping between 0 V and 1 V every second
+    Step
+}
+
+/// How the value of a channel follows its signal
+#[derive(Copy, Clone, Debug)]
+struct Response {
+    /// Value of the channel is `offset + scale*signal`
+    offset : f64,
+    scale : f64,
+    /// Whether the channel picks up mains hum
+    hum : bool
+}
+
+#[derive(Debug)]
+struct SimulatedChannel {
+    name : String,
+    signal : Signal,
+    /// Value of the channel is `offset + scale*signal`
+    offset : f64,
+    scale : f64,
+    /// Whether the channel picks up mains hum
+    hum : bool,
+    min : f64,
+    max : f64
+}
+
+/// Pure-Rust stand-in for a DAQ device, paced by the wall clock
+///
+/// Samples become available at the configured rate after `start`, just like on hardware, so
+/// reads block until the requested samples have been "acquired", and a continuous acquisition
+/// that isn't read fast enough overflows its input buffer. Single-ended channels
+/// (RSE, NRSE) pick up a small amount of mains hum that differential channels reject, and
+/// every value is clipped to the channel's input range. Channels measuring other quantities
+/// follow their signal scaled to a plausible reading, e.g. a few degrees around room
+/// temperature. Analog triggers watch a channel of the task, digital triggers a line that is
+/// high every other second.
+#[derive(Debug)]
+pub struct SimulatedBackend {
+    signals : Vec<Signal>,
+    channels : Vec<SimulatedChannel>,
+    sample_rate : f64,
+    mode : SampleMode,
+    /// Samples per channel to acquire (finite) or input buffer size (continuous)
+    sample_count : u64,
+    /// Moment the device was created, signals are functions of time since then
+    origin : Instant,
+    /// Time of the last start and index of the first sample since `origin`
+    started : Option<(Instant, u64)>,
+    /// Wall clock time of the last start, reported as the first sample timestamp if enabled
+    start_timestamp : Option<DateTime<Utc>>,
+    first_sample_timestamp : bool,
+    start_trigger : Option<Trigger>,
+    /// Reference trigger and number of pretrigger samples
+    reference_trigger : Option<(Trigger, u64)>,
+    /// Samples per channel read since last start
+    acquired : u64,
+    rng : u64
+}
+
+impl SimulatedBackend {
+    /// Create a simulated device, channels are assigned `signals` in turn
+    pub fn new(signals : &[Signal]) -> SimulatedBackend {
+        let signals = if signals.is_empty() { vec![Signal::Sine] } else { signals.to_vec() };
+        SimulatedBackend {
+            signals : signals,
+            channels : Vec::new(),
+            sample_rate : 1000.0,
+            mode : SampleMode::Finite,
+            sample_count : 1000,
+            origin : Instant::now(),
+            started : None,
+            start_timestamp : None,
+            first_sample_timestamp : false,
+            start_trigger : None,
+            reference_trigger : None,
+            acquired : 0,
+            rng : 0x2545_f491_4f6c_dd1d
+        }
+    }
+
+    /// Uniform random number in [0, 1), xorshift64*
+    fn uniform(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        let r = self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d);
+        (r >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Approximately normal random number with zero mean and unit variance
+    fn gaussian(&mut self) -> f64 {
+        // Irwin-Hall: sum of 12 uniforms has variance 1
+        let mut sum = 0.0;
+        for _ in 0..12 {
+            sum += self.uniform();
+        }
+        sum - 6.0
+    }
+
+    /// Samples per channel clocked in by the device since start
+    fn clocked(&self) -> u64 {
+        match self.started {
+            Some((start_time, _)) => {
+                let clocked = (start_time.elapsed().as_secs_f64()*self.sample_rate) as u64;
+                match self.mode {
+                    SampleMode::Finite => std::cmp::min(clocked, self.sample_count),
+                    SampleMode::Continuous => clocked,
+                }
+            }
+            None => self.acquired
+        }
+    }
+
+    /// Samples per channel clocked in but not read yet. Reads of a reference triggered
+    /// acquisition wait for the trigger, the pretrigger samples become available with it
+    fn available(&self) -> u64 {
+        if let (Some((_, pretrigger_samples)), Some((start_time, _))) = (&self.reference_trigger, self.started) {
+            if start_time.elapsed().as_secs_f64()*self.sample_rate < *pretrigger_samples as f64 {
+                return 0;
+            }
+        }
+        self.clocked().saturating_sub(self.acquired)
+    }
+
+    /// Read a full buffer, or the scans clocked in by the time `timeout` [s] elapses. The
+    /// number of scans read and whether the timeout elapsed
+    fn read_scans(&mut self, buffer : &mut [f64], timeout : f64) -> Result<(usize, bool), DAQmxError> {
+        // Like DAQmx, reading a task that isn't running starts it implicitly
+        if self.started.is_none() {
+            self.start()?;
+        }
+        let (start_time, first) = self.started.unwrap();
+        let channels = self.channels.len();
+        if channels == 0 {
+            return Ok((0, false));
+        }
+
+        let mut count = match self.mode {
+            // Finite acquisition ends after sample_count samples per channel
+            SampleMode::Finite => std::cmp::min((buffer.len()/channels) as u64, self.sample_count - self.acquired),
+            SampleMode::Continuous => {
+                // Samples clocked in but not read yet must fit in the input buffer
+                if self.clocked() - self.acquired > self.sample_count {
+                    return Err(error("DAQmxReadAnalogF64", ERR_SAMPLES_NO_LONGER_AVAILABLE, "The application is not able to keep up with the hardware acquisition."));
+                }
+                (buffer.len()/channels) as u64
+            }
+        };
+
+        // Wait until the requested samples have been clocked in
+        let ready_at = start_time + Duration::from_secs_f64((self.acquired + count) as f64/self.sample_rate);
+        let wait = ready_at.saturating_duration_since(Instant::now());
+        let timed_out = timeout >= 0.0 && wait.as_secs_f64() > timeout;
+        if timed_out {
+            std::thread::sleep(Duration::from_secs_f64(timeout));
+            count = std::cmp::min(count, self.available());
+        } else {
+            std::thread::sleep(wait);
+        }
+
+        for scan in 0..count as usize {
+            let t = (first + self.acquired + scan as u64) as f64/self.sample_rate;
+            for channel in 0..channels {
+                buffer[scan*channels + channel] = self.sample(channel, t);
+            }
+        }
+        self.acquired += count;
+
+        Ok((count as usize, timed_out))
+    }
+
+    /// Value of channel `index` at time `t` seconds since `origin`
+    fn sample(&mut self, index : usize, t : f64) -> f64 {
+        let channel = &self.channels[index];
+        let (signal, offset, scale, hum, min, max) = (channel.signal, channel.offset, channel.scale, channel.hum, channel.min, channel.max);
+        let mut value = match signal {
+            Signal::Sine => (2.0*PI*t + index as f64*PI/4.0).sin(),
+            Signal::Noise => 0.1*self.gaussian(),
+            Signal::Step => if (t as u64).is_multiple_of(2) { 0.0 } else { 1.0 },
+        };
+        if hum {
+            value += HUM_AMPLITUDE*(2.0*PI*HUM_FREQUENCY*t).sin();
+        }
+        (offset + scale*value).clamp(min, max)
+    }
+
+    /// Index of the channel an analog trigger watches
+    fn trigger_channel(&self, function : &'static str, trigger : &Trigger) -> Result<Option<usize>, DAQmxError> {
+        match *trigger {
+            Trigger::DigitalEdge { .. } => return Ok(None),
+            Trigger::AnalogWindow { bottom, top, .. } if bottom.partial_cmp(&top) != Some(Ordering::Less) => {
+                return Err(error(function, ERR_INVALID_ATTRIBUTE_VALUE, "Window top must be greater than window bottom."));
+            }
+            _ => {}
+        }
+        match self.channels.iter().position(|channel| channel.name == trigger.source()) {
+            Some(index) => Ok(Some(index)),
+            None => Err(error(function, ERR_PHYSICAL_CHAN_DOES_NOT_EXIST, "Analog trigger source must be a channel in the task.")),
+        }
+    }
+
+    /// Index of the first sample from `from` on at which the trigger condition is met
+    fn find_trigger(&mut self, trigger : &Trigger, from : u64) -> Option<u64> {
+        let channel = self.trigger_channel("DAQmxStartTask", trigger).ok()?;
+        let mut previous : Option<bool> = None;
+        let mut previous_value = 0.0;
+        for index in from..from + (TRIGGER_SEARCH_TIME*self.sample_rate) as u64 {
+            let t = index as f64/self.sample_rate;
+            let fired = match *trigger {
+                Trigger::DigitalEdge { edge, .. } => {
+                    let high = (t as u64) % 2 == 1;
+                    let fired = previous.is_some_and(|was_high| was_high != high && high == (edge == Edge::Rising));
+                    previous = Some(high);
+                    fired
+                }
+                Trigger::AnalogEdge { edge, level, .. } => {
+                    let value = self.sample(channel.unwrap(), t);
+                    let fired = previous.is_some() && match edge {
+                        Edge::Rising => previous_value < level && value >= level,
+                        Edge::Falling => previous_value > level && value <= level,
+                    };
+                    previous = Some(true);
+                    previous_value = value;
+                    fired
+                }
+                Trigger::AnalogWindow { when, bottom, top, .. } => {
+                    let value = self.sample(channel.unwrap(), t);
+                    let inside = bottom <= value && value <= top;
+                    let fired = previous.is_some_and(|was_inside| was_inside != inside && inside == (when == WindowCondition::Entering));
+                    previous = Some(inside);
+                    fired
+                }
+            };
+            if fired {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// Add a channel for every physical channel in the list, reporting errors as `function`
+    fn add_channels(&mut self, function : &'static str, channels : &str, min : f64, max : f64, response : Response) -> Result<(), DAQmxError> {
+        if min.partial_cmp(&max) != Some(Ordering::Less) {
+            return Err(error(function, ERR_INVALID_ATTRIBUTE_VALUE, "Minimum value must be less than maximum value."));
+        }
+        // Ranges such as Dev1/ai0:3 create a virtual channel per physical channel, as with DAQmx
+        let names = expand_physical_channels(channels).map_err(|err| error(function, ERR_PHYSICAL_CHAN_DOES_NOT_EXIST, &err))?;
+        for name in names {
+            let signal = self.signals[self.channels.len() % self.signals.len()];
+            self.channels.push(SimulatedChannel {
+                name : name,
+                signal : signal,
+                offset : response.offset,
+                scale : response.scale,
+                hum : response.hum,
+                min : min,
+                max : max
+            });
+        }
+        Ok(())
+    }
+
+    /// Add temperature channels in `units`
+    fn add_temperature_channels(&mut self, function : &'static str, channels : &str, min : f64, max : f64, units : TemperatureUnits) -> Result<(), DAQmxError> {
+        let offset = units.from_celsius(AMBIENT_TEMPERATURE);
+        let scale = units.from_celsius(TEMPERATURE_SWING) - units.from_celsius(0.0);
+        self.add_channels(function, channels, min, max, Response { offset : offset, scale : scale, hum : false })
+    }
+}
+
+impl AcquisitionBackend for SimulatedBackend {
+    fn device_names(&self) -> Result<Vec<String>, DAQmxError> {
+        Ok(DEVICES.iter().map(|device| String::from(device.name)).collect())
+    }
+
+    fn device_info(&self, device : &str) -> Result<DeviceInfo, DAQmxError> {
+        let device = find_device(device).ok_or_else(|| error("DAQmxGetDevProductType", ERR_INVALID_DEVICE_ID, "Device identifier is invalid."))?;
+        Ok(DeviceInfo {
+            name : String::from(device.name),
+            product_type : String::from(device.product_type),
+            serial_number : device.serial_number,
+            modules : device.modules.iter().map(|module| String::from(*module)).collect(),
+            ai_channels : (0..device.ai_channels).map(|i| format!("{}/ai{}", device.name, i)).collect()
+        })
+    }
+
+    fn voltage_ranges(&self, device : &str) -> Result<Vec<Range>, DAQmxError> {
+        let ranges = find_device(device).map_or(&VOLTAGE_RANGES[..], |device| device.ranges);
+        Ok(ranges.iter().map(|&(min, max)| Range { min : min, max : max }).collect())
+    }
+
+    fn terminal_configs(&self, physical : &str) -> Result<Vec<MeasurementMode>, DAQmxError> {
+        match find_device(physical) {
+            Some(device) => Ok(device.terminals.to_vec()),
+            None => Ok(vec![MeasurementMode::RSE, MeasurementMode::NRSE, MeasurementMode::DIFF, MeasurementMode::PSEUDODIFF]),
+        }
+    }
+
+    fn create_voltage_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64) -> Result<(), DAQmxError> {
+        let hum = matches!(mode, MeasurementMode::RSE | MeasurementMode::NRSE);
+        self.add_channels("DAQmxCreateAIVoltageChan", channels, min, max, Response { offset : 0.0, scale : 1.0, hum : hum })
+    }
+
+    fn create_thermocouple_channels(&mut self, channels : &str, min : f64, max : f64, thermocouple : &Thermocouple) -> Result<(), DAQmxError> {
+        if thermocouple.cjc_source == CjcSource::Channel && thermocouple.cjc_channel.as_deref().unwrap_or("").trim().is_empty() {
+            return Err(error("DAQmxCreateAIThrmcplChan", ERR_INVALID_ATTRIBUTE_VALUE, "CJC channel must be specified when the CJC source is a channel."));
+        }
+        self.add_temperature_channels("DAQmxCreateAIThrmcplChan", channels, min, max, thermocouple.units)
+    }
+
+    fn create_rtd_channels(&mut self, channels : &str, min : f64, max : f64, rtd : &Rtd) -> Result<(), DAQmxError> {
+        if rtd.r0.is_nan() || rtd.r0 <= 0.0 {
+            return Err(error("DAQmxCreateAIRTDChan", ERR_INVALID_ATTRIBUTE_VALUE, "R0 must be greater than zero."));
+        }
+        self.add_temperature_channels("DAQmxCreateAIRTDChan", channels, min, max, rtd.units)
+    }
+
+    fn create_current_channels(&mut self, channels : &str, mode : MeasurementMode, min : f64, max : f64, current : &Current) -> Result<(), DAQmxError> {
+        if current.shunt == ShuntLocation::External && (current.shunt_resistance.is_nan() || current.shunt_resistance <= 0.0) {
+            return Err(error("DAQmxCreateAICurrentChan", ERR_INVALID_ATTRIBUTE_VALUE, "External shunt resistor value must be greater than zero."));
+        }
+        let hum = matches!(mode, MeasurementMode::RSE | MeasurementMode::NRSE);
+        self.add_channels("DAQmxCreateAICurrentChan", channels, min, max, Response { offset : 0.012, scale : CURRENT_SWING, hum : hum })
+    }
+
+    fn create_strain_channels(&mut self, channels : &str, min : f64, max : f64, strain : &StrainGage) -> Result<(), DAQmxError> {
+        if strain.gage_factor.is_nan() || strain.gage_factor <= 0.0 || strain.nominal_resistance.is_nan() || strain.nominal_resistance <= 0.0 {
+            return Err(error("DAQmxCreateAIStrainGageChan", ERR_INVALID_ATTRIBUTE_VALUE, "Gage factor and nominal gage resistance must be greater than zero."));
+        }
+        self.add_channels("DAQmxCreateAIStrainGageChan", channels, min, max, Response { offset : 0.0, scale : STRAIN_SWING, hum : false })
+    }
+
+    fn create_bridge_channels(&mut self, channels : &str, min : f64, max : f64, bridge : &Bridge) -> Result<(), DAQmxError> {
+        if bridge.nominal_resistance.is_nan() || bridge.nominal_resistance <= 0.0 {
+            return Err(error("DAQmxCreateAIBridgeChan", ERR_INVALID_ATTRIBUTE_VALUE, "Nominal bridge resistance must be greater than zero."));
+        }
+        let scale = bridge.units.from_millivolts_per_volt(BRIDGE_SWING);
+        self.add_channels("DAQmxCreateAIBridgeChan", channels, min, max, Response { offset : 0.0, scale : scale, hum : false })
+    }
+
+    fn create_accelerometer_channels(&mut self, channels : &str, _mode : MeasurementMode, min : f64, max : f64, accelerometer : &Accelerometer) -> Result<(), DAQmxError> {
+        if accelerometer.sensitivity.is_nan() || accelerometer.sensitivity <= 0.0 {
+            return Err(error("DAQmxCreateAIAccelChan", ERR_INVALID_ATTRIBUTE_VALUE, "Sensitivity must be greater than zero."));
+        }
+        // One g per volt of signal
+        let scale = accelerometer.units.from_g(1.0);
+        self.add_channels("DAQmxCreateAIAccelChan", channels, min, max, Response { offset : 0.0, scale : scale, hum : false })
+    }
+
+    fn num_channels(&self) -> Result<u32, DAQmxError> {
+        Ok(self.channels.len() as u32)
+    }
+
+    fn channel_names(&self) -> Result<Vec<String>, DAQmxError> {
+        Ok(self.channels.iter().map(|channel| channel.name.clone()).collect())
+    }
+
+    fn configure_timing(&mut self, sample_rate : f64, mode : SampleMode, sample_count : u64) -> Result<(), DAQmxError> {
+        if sample_rate.is_nan() || sample_rate <= 0.0 || sample_count == 0 {
+            return Err(error("DAQmxCfgSampClkTiming", ERR_INVALID_ATTRIBUTE_VALUE, "Sample rate and samples per channel must be greater than zero."));
+        }
+        self.sample_rate = sample_rate;
+        self.mode = mode;
+        self.sample_count = sample_count;
+        Ok(())
+    }
+
+    fn configure_start_trigger(&mut self, trigger : &Trigger) -> Result<(), DAQmxError> {
+        self.trigger_channel(start_trigger_function(trigger), trigger)?;
+        self.start_trigger = Some(trigger.clone());
+        Ok(())
+    }
+
+    fn configure_reference_trigger(&mut self, trigger : &Trigger, pretrigger_samples : u64) -> Result<(), DAQmxError> {
+        let function = reference_trigger_function(trigger);
+        self.trigger_channel(function, trigger)?;
+        if self.mode != SampleMode::Finite || pretrigger_samples >= self.sample_count {
+            return Err(error(function, ERR_INVALID_ATTRIBUTE_VALUE, "Reference triggers require a finite acquisition with more samples than pretrigger samples."));
+        }
+        if u32::try_from(pretrigger_samples).is_err() {
+            return Err(error(function, ERR_INVALID_ATTRIBUTE_VALUE, "Pretrigger samples must fit in a 32-bit unsigned integer."));
+        }
+        self.reference_trigger = Some((trigger.clone(), pretrigger_samples));
+        Ok(())
+    }
+
+    fn enable_first_sample_timestamp(&mut self) -> Result<(), DAQmxError> {
+        self.first_sample_timestamp = true;
+        Ok(())
+    }
+
+    fn first_sample_timestamp(&self) -> Result<Option<DateTime<Utc>>, DAQmxError> {
+        if !self.first_sample_timestamp {
+            return Ok(None);
+        }
+        Ok(self.start_timestamp)
+    }
+
+    fn start(&mut self) -> Result<(), DAQmxError> {
+        let timestamp = Utc::now();
+        let now = Instant::now();
+        let first = (now.duration_since(self.origin).as_secs_f64()*self.sample_rate) as u64;
+        self.acquired = 0;
+
+        // A triggered acquisition starts at the trigger, or the pretrigger samples before it
+        let triggered = match (self.start_trigger.clone(), self.reference_trigger.clone()) {
+            (Some(trigger), _) => Some(self.find_trigger(&trigger, first)),
+            (None, Some((trigger, pretrigger_samples))) => Some(self.find_trigger(&trigger, first + pretrigger_samples).map(|index| index - pretrigger_samples)),
+            (None, None) => None,
+        };
+        let (start_time, first) = match triggered {
+            None => (now, first),
+            Some(Some(first)) => (self.origin + Duration::from_secs_f64(first as f64/self.sample_rate), first),
+            // The trigger never comes, reads time out
+            Some(None) => (now + Duration::from_secs(365*24*3600), first),
+        };
+        self.started = Some((start_time, first));
+        self.start_timestamp = Some(match start_time.checked_duration_since(now) {
+            Some(delay) => timestamp + delay,
+            None => timestamp - now.duration_since(start_time),
+        });
+        Ok(())
+    }
+
+    fn read(&mut self, buffer : &mut [f64], timeout : f64) -> Result<usize, DAQmxError> {
+        // Like DAQmx, the scans read before the timeout are gone with the error
+        match self.read_scans(buffer, timeout)? {
+            (read, false) => Ok(read),
+            (_, true) => Err(error("DAQmxReadAnalogF64", ERR_SAMPLES_NOT_YET_AVAILABLE, "Some or all of the samples requested have not yet been acquired.")),
+        }
+    }
+
+    fn read_partial(&mut self, buffer : &mut [f64], timeout : f64) -> Result<usize, DAQmxError> {
+        self.read_scans(buffer, timeout).map(|(read, _)| read)
+    }
+
+    fn total_acquired(&self) -> Result<u64, DAQmxError> {
+        Ok(self.clocked())
+    }
+
+    fn stop(&mut self) -> Result<(), DAQmxError> {
+        self.started = None;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backend() -> SimulatedBackend {
+        let mut backend = SimulatedBackend::new(&[]);
+        backend.create_voltage_channels("cDAQSimMod1/ai0:1", MeasurementMode::DIFF, -10.0, 10.0).unwrap();
+        backend.configure_timing(1000.0, SampleMode::Continuous, 10_000).unwrap();
+        backend.start().unwrap();
+        backend
+    }
+
+    #[test]
+    fn partial_read_keeps_the_scans() {
+        let mut backend = backend();
+        let mut buffer = vec![f64::NAN; 2000];
+        let read = backend.read_partial(&mut buffer, 0.05).unwrap();
+        assert!((40..1000).contains(&read), "{} scans read", read);
+        assert!(buffer[..2*read].iter().all(|value| value.is_finite()));
+        assert!(buffer[2*read..].iter().all(|value| value.is_nan()));
+        assert_eq!(backend.acquired, read as u64);
+    }
+
+    #[test]
+    fn timed_out_read_loses_the_scans() {
+        let mut backend = backend();
+        let mut buffer = vec![0.0; 2000];
+        let err = backend.read(&mut buffer, 0.05).unwrap_err();
+        assert_eq!(err.code(), ERR_SAMPLES_NOT_YET_AVAILABLE);
+        // Like DAQmx, the scans acquired before the timeout were read all the same
+        assert!(backend.acquired >= 40);
+        assert!(backend.acquired <= backend.total_acquired().unwrap());
+    }
+}
@@ -0,0 +1,369 @@
+//! Scans of one read, borrowed from the buffers of the task or copied out of them
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::prelude::*;
+
+use crate::DAQVTask;
+use crate::backend::ERR_SAMPLES_NOT_YET_AVAILABLE;
+use crate::error::DAQmxError;
+use crate::schedule::Schedule;
+use crate::sink::{ChannelInfo, StreamInfo};
+
+/// View of the scans read by one call, valid until the next read
+///
+/// Samples are available both interleaved, one scan after the other as sinks take them, and
+/// de-interleaved, one contiguous column per output channel.
+#[derive(Copy, Clone)]
+pub struct Batch<'a> {
+    channels : &'a [ChannelInfo],
+    timestamps : &'a [DateTime<Local>],
+    /// Scans one after the other, one value per output column
+    samples : &'a [f64],
+    /// Columns one after the other, each `stride` values long
+    columns : &'a [f64],
+    stride : usize
+}
+
+impl<'a> Batch<'a> {
+    /// Batch of `timestamps.len()` scans, `columns` holds the column of every channel at a
+    /// multiple of `stride`
+    pub(crate) fn new(channels : &'a [ChannelInfo], timestamps : &'a [DateTime<Local>], samples : &'a [f64], columns : &'a [f64], stride : usize) -> Batch<'a> {
+        debug_assert_eq!(samples.len(), timestamps.len()*channels.len());
+        Batch {
+            channels : channels,
+            timestamps : timestamps,
+            samples : samples,
+            columns : columns,
+            stride : stride
+        }
+    }
+
+    /// Number of scans
+    pub fn len(&self) -> usize {
+        self.timestamps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamps.is_empty()
+    }
+
+    /// Output columns, one per channel plus one per kept raw value
+    pub fn channels(&self) -> &'a [ChannelInfo] {
+        self.channels
+    }
+
+    /// Time of every scan
+    pub fn timestamps(&self) -> &'a [DateTime<Local>] {
+        self.timestamps
+    }
+
+    pub fn first_timestamp(&self) -> Option<DateTime<Local>> {
+        self.timestamps.first().copied()
+    }
+
+    pub fn last_timestamp(&self) -> Option<DateTime<Local>> {
+        self.timestamps.last().copied()
+    }
+
+    /// Samples of every scan one after the other, as sinks write them
+    pub fn samples(&self) -> &'a [f64] {
+        self.samples
+    }
+
+    /// Samples of scan `index`, one per output column
+    pub fn scan(&self, index : usize) -> &'a [f64] {
+        let width = self.channels.len();
+        &self.samples[index*width..(index + 1)*width]
+    }
+
+    /// Time and samples of every scan
+    pub fn rows(&self) -> impl ExactSizeIterator<Item = (DateTime<Local>, &'a [f64])> + 'a {
+        let width = self.channels.len();
+        self.timestamps.iter().copied().zip(self.samples.chunks_exact(width))
+    }
+
+    /// Samples of output column `index`, one per scan
+    pub fn column(&self, index : usize) -> &'a [f64] {
+        assert!(index < self.channels.len(), "column {} out of range for {} channels", index, self.channels.len());
+        &self.columns[index*self.stride..index*self.stride + self.len()]
+    }
+
+    /// Samples of the output column named `name`
+    pub fn channel(&self, name : &str) -> Option<&'a [f64]> {
+        self.channels.iter().position(|channel| channel.name == name).map(|index| self.column(index))
+    }
+
+    /// Every output column with its samples
+    pub fn columns(&self) -> impl ExactSizeIterator<Item = (&'a ChannelInfo, &'a [f64])> + 'a {
+        let batch = *self;
+        self.channels.iter().enumerate().map(move |(index, channel)| (channel, batch.column(index)))
+    }
+
+    /// The first `scans` scans, or all of them if there are fewer
+    pub fn truncate(&self, scans : usize) -> Batch<'a> {
+        let scans = std::cmp::min(scans, self.len());
+        Batch {
+            timestamps : &self.timestamps[..scans],
+            samples : &self.samples[..scans*self.channels.len()],
+            ..*self
+        }
+    }
+}
+
+/// Batch with copies of its scans, to be kept or sent elsewhere while the task reads on
+#[derive(Clone)]
+pub struct OwnedBatch {
+    /// Acquisition the scans belong to
+    info : Arc<StreamInfo>,
+    timestamps : Vec<DateTime<Local>>,
+    samples : Vec<f64>,
+    /// Columns one after the other, each one batch long
+    columns : Vec<f64>
+}
+
+impl OwnedBatch {
+    /// Copy the scans of `batch`, which belongs to the acquisition described by `info`
+    pub fn new(info : Arc<StreamInfo>, batch : &Batch) -> OwnedBatch {
+        OwnedBatch {
+            info : info,
+            timestamps : batch.timestamps().to_vec(),
+            samples : batch.samples().to_vec(),
+            columns : batch.columns().flat_map(|(_, samples)| samples.iter().copied()).collect()
+        }
+    }
+
+    pub fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    /// View of the scans, as the task returns them
+    pub fn as_batch(&self) -> Batch<'_> {
+        Batch::new(&self.info.channels, &self.timestamps, &self.samples, &self.columns, self.timestamps.len())
+    }
+}
+
+/// Iterator reading successive batches from a task, returned by [`DAQVTask::batches`]
+///
+/// Without a stop condition it reads forever. Read errors are yielded, reading carries on after
+/// a buffer overrun, as the task restarts itself, and ends after any other error.
+pub struct Batches<'a> {
+    task : &'a mut DAQVTask,
+    schedule : Schedule,
+    stop : Option<&'a AtomicUsize>,
+    /// Description of the acquisition, known after the first read
+    info : Option<Arc<StreamInfo>>,
+    /// Scheduled end, known once the time of the first sample is
+    end : Option<Option<DateTime<Local>>>,
+    /// Scans per channel yielded so far
+    yielded : u64,
+    /// Scans the device had acquired but not yet read when `stop` was set
+    pending : Option<u64>,
+    done : bool
+}
+
+impl<'a> Batches<'a> {
+    pub(crate) fn new(task : &'a mut DAQVTask) -> Batches<'a> {
+        Batches {
+            task : task,
+            schedule : Schedule::default(),
+            stop : None,
+            info : None,
+            end : None,
+            yielded : 0,
+            pending : None,
+            done : false
+        }
+    }
+
+    /// End when a stop condition of the schedule is met, the batch meeting it is cut short so
+    /// that exactly the scheduled samples are yielded. The start time is not waited for
+    pub fn scheduled(mut self, schedule : &Schedule) -> Batches<'a> {
+        self.schedule = schedule.clone();
+        self
+    }
+
+    /// End once `stop` is nonzero, after yielding the samples the device has already acquired
+    /// by then
+    pub fn until(mut self, stop : &'a AtomicUsize) -> Batches<'a> {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Read the next batch, the samples of the stopped acquisition still pending once stopped.
+    /// None when there is nothing left to read
+    fn read(&mut self) -> Option<Result<usize, DAQmxError>> {
+        if self.stop.is_none_or(|stop| stop.load(Ordering::Relaxed) == 0) {
+            return self.task.acquire_samples_until(self.stop).map(|batch| batch.map(|batch| batch.len())).transpose();
+        }
+        // The device keeps acquiring until the task is stopped
+        let pending = match self.pending {
+            Some(pending) => pending,
+            None => match self.task.pending() {
+                Ok(pending) => pending,
+                Err(err) => {
+                    self.done = true;
+                    return Some(Err(err));
+                }
+            },
+        };
+        if pending == 0 {
+            return None;
+        }
+        match self.task.read_pending(pending).map(|batch| batch.len()) {
+            Ok(0) => None,
+            Ok(read) => {
+                self.pending = Some(pending - read as u64);
+                Some(Ok(read))
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+impl Iterator for Batches<'_> {
+    type Item = Result<OwnedBatch, DAQmxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let read = match self.read() {
+            Some(Ok(read)) => read,
+            Some(Err(err)) => {
+                self.done = !err.is_overrun();
+                return Some(Err(err));
+            }
+            None => {
+                self.done = true;
+                return None;
+            }
+        };
+
+        let info = match &self.info {
+            Some(info) => info.clone(),
+            None => match self.task.stream_info() {
+                Some(info) => self.info.insert(Arc::new(info)).clone(),
+                None => {
+                    self.done = true;
+                    return Some(Err(DAQmxError::new("DAQmxGetFirstSampTimestampVal", ERR_SAMPLES_NOT_YET_AVAILABLE, String::from("Time of the first sample not known after a read."))));
+                }
+            },
+        };
+        let end = *self.end.get_or_insert_with(|| self.schedule.end(info.epoch));
+        let mut batch = self.task.last_batch();
+        if let Some(total_samples) = self.schedule.total_samples {
+            batch = batch.truncate((total_samples - self.yielded) as usize);
+        }
+        if let Some(end) = end {
+            batch = batch.truncate(batch.timestamps().partition_point(|timestamp| *timestamp < end));
+        }
+        self.yielded += batch.len() as u64;
+        self.done = batch.len() < read || self.schedule.total_samples == Some(self.yielded);
+        Some(Ok(OwnedBatch::new(info, &batch)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeDelta;
+
+    /// Three scans of two channels, the columns spaced further apart than the batch is long
+    struct Fixture {
+        channels : Vec<ChannelInfo>,
+        timestamps : Vec<DateTime<Local>>,
+        samples : Vec<f64>,
+        columns : Vec<f64>
+    }
+
+    const STRIDE : usize = 5;
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let channel = |name : &str| ChannelInfo { name : String::from(name), physical : format!("Dev1/{}", name), units : String::from("V"), min : -10.0, max : 10.0, terminal : None };
+            let t0 = Local.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+            let mut columns = vec![f64::NAN; 2*STRIDE];
+            columns[..3].copy_from_slice(&[1.0, 2.0, 3.0]);
+            columns[STRIDE..STRIDE + 3].copy_from_slice(&[10.0, 20.0, 30.0]);
+            Fixture {
+                channels : vec![channel("ai0"), channel("ai1")],
+                timestamps : (0..3).map(|index| t0 + TimeDelta::milliseconds(10*index)).collect(),
+                samples : vec![1.0, 10.0, 2.0, 20.0, 3.0, 30.0],
+                columns : columns
+            }
+        }
+
+        fn batch(&self) -> Batch<'_> {
+            Batch::new(&self.channels, &self.timestamps, &self.samples, &self.columns, STRIDE)
+        }
+    }
+
+    #[test]
+    fn columns() {
+        let fixture = Fixture::new();
+        let batch = fixture.batch();
+        assert_eq!(batch.len(), 3);
+        assert!(!batch.is_empty());
+        assert_eq!(batch.column(0), &[1.0, 2.0, 3.0]);
+        assert_eq!(batch.column(1), &[10.0, 20.0, 30.0]);
+        assert_eq!(batch.channel("ai1"), Some(&[10.0, 20.0, 30.0][..]));
+        assert_eq!(batch.channel("ai2"), None);
+        let columns : Vec<_> = batch.columns().map(|(channel, samples)| (channel.name.as_str(), samples)).collect();
+        assert_eq!(columns, [("ai0", &[1.0, 2.0, 3.0][..]), ("ai1", &[10.0, 20.0, 30.0][..])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "column 2 out of range")]
+    fn column_out_of_range() {
+        let fixture = Fixture::new();
+        fixture.batch().column(2);
+    }
+
+    #[test]
+    fn scans() {
+        let fixture = Fixture::new();
+        let batch = fixture.batch();
+        assert_eq!(batch.scan(1), &[2.0, 20.0]);
+        let rows : Vec<_> = batch.rows().collect();
+        assert_eq!(rows, [(fixture.timestamps[0], &[1.0, 10.0][..]), (fixture.timestamps[1], &[2.0, 20.0][..]), (fixture.timestamps[2], &[3.0, 30.0][..])]);
+        assert_eq!(batch.first_timestamp(), Some(fixture.timestamps[0]));
+        assert_eq!(batch.last_timestamp(), Some(fixture.timestamps[2]));
+    }
+
+    #[test]
+    fn truncate() {
+        let fixture = Fixture::new();
+        let batch = fixture.batch().truncate(2);
+        assert_eq!(batch.len(), 2);
+        assert_eq!(batch.samples(), &[1.0, 10.0, 2.0, 20.0]);
+        assert_eq!(batch.column(1), &[10.0, 20.0]);
+        assert_eq!(batch.rows().len(), 2);
+        assert_eq!(batch.last_timestamp(), Some(fixture.timestamps[1]));
+
+        // More scans than the batch holds keeps all of them
+        assert_eq!(fixture.batch().truncate(10).len(), 3);
+
+        let empty = fixture.batch().truncate(0);
+        assert!(empty.is_empty());
+        assert!(empty.column(0).is_empty());
+        assert_eq!(empty.rows().len(), 0);
+        assert_eq!(empty.first_timestamp(), None);
+        assert_eq!(empty.channel("ai0"), Some(&[][..]));
+    }
+
+    #[test]
+    fn owned_batch() {
+        let fixture = Fixture::new();
+        let info = Arc::new(StreamInfo { channels : fixture.channels.clone(), sample_rate : 100.0, batch_size : 3, epoch : fixture.timestamps[0] });
+        let owned = OwnedBatch::new(info, &fixture.batch().truncate(2));
+        let batch = owned.as_batch();
+        assert_eq!(batch.samples(), &[1.0, 10.0, 2.0, 20.0]);
+        assert_eq!(batch.column(0), &[1.0, 2.0]);
+        assert_eq!(batch.column(1), &[10.0, 20.0]);
+    }
+}
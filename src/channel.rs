@@ -0,0 +1,825 @@
+//! Per-channel acquisition settings
+
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fmt;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::MeasurementMode;
+use crate::scale::Scale;
+
+/// One entry of the channel list, creating one or more virtual channels with the same settings
+///
+/// In configuration files the sensor settings of a channel that doesn't measure voltage go
+/// in a table named after the kind of channel, e.g. `thermocouple = { type = "k" }`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ChannelTable", into = "ChannelTable")]
+pub struct ChannelConfig {
+    /// Physical channels, e.g. Dev1/ai0, a range Dev1/ai0:3 or a comma-separated list of those
+    pub physical : String,
+    /// Name of the channel in the output instead of the physical channel name, numbered
+    /// from 0 if the entry has several physical channels
+    pub name : Option<String>,
+    /// Input range in the units of the channel, the default of the kind of channel if None
+    pub range : Option<Range>,
+    /// Terminal configuration of voltage, current and accelerometer channels
+    pub terminal : MeasurementMode,
+    pub kind : ChannelKind,
+    /// Conversion of the samples to engineering units
+    pub scale : Option<Scale>
+}
+
+impl ChannelConfig {
+    /// First physical channel of the entry, e.g. Dev1/ai0 for Dev1/ai0:3
+    pub fn first_physical(&self) -> &str {
+        let first = self.physical.trim().split(',').next().unwrap_or("");
+        first.split(':').next().unwrap_or(first).trim()
+    }
+
+    /// Device part of the physical channel name
+    pub fn device(&self) -> &str {
+        let physical = self.physical.trim().trim_start_matches('/');
+        physical.split('/').next().unwrap_or(physical)
+    }
+
+    /// One entry per physical channel, named like the virtual channels the entry creates
+    pub fn expand(&self) -> Result<Vec<ChannelConfig>, String> {
+        let physical = expand_physical_channels(&self.physical)?;
+        let count = physical.len();
+        Ok(physical.into_iter().enumerate().map(|(i, physical)| ChannelConfig {
+            physical : physical,
+            name : self.name.as_ref().map(|name| if count == 1 { name.clone() } else { format!("{}{}", name, i) }),
+            ..self.clone()
+        }).collect())
+    }
+
+    /// Input range in the units of the channel
+    pub fn range(&self) -> Range {
+        self.range.unwrap_or_else(|| self.kind.default_range())
+    }
+}
+
+/// Layout of a channel in configuration files, with one optional table per kind of channel
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChannelTable {
+    physical : String,
+    #[serde(default, alias = "alias", skip_serializing_if = "Option::is_none")]
+    name : Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    range : Option<Range>,
+    #[serde(default)]
+    terminal : MeasurementMode,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    thermocouple : Option<Thermocouple>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rtd : Option<Rtd>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    current : Option<Current>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    strain : Option<StrainGage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bridge : Option<Bridge>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    accelerometer : Option<Accelerometer>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    scale : Option<Scale>
+}
+
+impl TryFrom<ChannelTable> for ChannelConfig {
+    type Error = String;
+
+    fn try_from(table : ChannelTable) -> Result<ChannelConfig, String> {
+        let kinds : Vec<ChannelKind> = [
+            table.thermocouple.map(ChannelKind::Thermocouple),
+            table.rtd.map(ChannelKind::Rtd),
+            table.current.map(ChannelKind::Current),
+            table.strain.map(ChannelKind::Strain),
+            table.bridge.map(ChannelKind::Bridge),
+            table.accelerometer.map(ChannelKind::Accelerometer)
+        ].into_iter().flatten().collect();
+        if kinds.len() > 1 {
+            return Err(format!("{}: only one of thermocouple, rtd, current, strain, bridge and accelerometer can be given", table.physical));
+        }
+        let kind = kinds.into_iter().next().unwrap_or_default();
+        Ok(ChannelConfig {
+            physical : table.physical,
+            name : table.name,
+            range : table.range,
+            terminal : table.terminal,
+            kind : kind,
+            scale : table.scale
+        })
+    }
+}
+
+impl From<ChannelConfig> for ChannelTable {
+    fn from(channel : ChannelConfig) -> ChannelTable {
+        let mut table = ChannelTable {
+            physical : channel.physical,
+            name : channel.name,
+            range : channel.range,
+            terminal : channel.terminal,
+            thermocouple : None,
+            rtd : None,
+            current : None,
+            strain : None,
+            bridge : None,
+            accelerometer : None,
+            scale : channel.scale
+        };
+        match channel.kind {
+            ChannelKind::Voltage => {}
+            ChannelKind::Thermocouple(thermocouple) => table.thermocouple = Some(thermocouple),
+            ChannelKind::Rtd(rtd) => table.rtd = Some(rtd),
+            ChannelKind::Current(current) => table.current = Some(current),
+            ChannelKind::Strain(strain) => table.strain = Some(strain),
+            ChannelKind::Bridge(bridge) => table.bridge = Some(bridge),
+            ChannelKind::Accelerometer(accelerometer) => table.accelerometer = Some(accelerometer),
+        }
+        table
+    }
+}
+
+/// What a channel measures, with the settings of its sensor
+#[derive(Clone, PartialEq, Debug, Default)]
+pub enum ChannelKind {
+    /// Voltage [V]
+    #[default]
+    Voltage,
+    /// Temperature measured with a thermocouple
+    Thermocouple(Thermocouple),
+    /// Temperature measured with a resistance temperature detector
+    Rtd(Rtd),
+    /// Current [A], e.g. from a 4-20 mA transmitter
+    Current(Current),
+    /// Strain measured with a strain gage bridge [strain]
+    Strain(StrainGage),
+    /// Ratiometric output of a bridge sensor such as a load cell
+    Bridge(Bridge),
+    /// Acceleration measured with an IEPE accelerometer
+    Accelerometer(Accelerometer)
+}
+
+impl ChannelKind {
+    /// Unit of the samples
+    pub fn units(&self) -> &'static str {
+        match self {
+            ChannelKind::Voltage => "V",
+            ChannelKind::Thermocouple(thermocouple) => thermocouple.units.symbol(),
+            ChannelKind::Rtd(rtd) => rtd.units.symbol(),
+            ChannelKind::Current(_) => "A",
+            ChannelKind::Strain(_) => "strain",
+            ChannelKind::Bridge(bridge) => bridge.units.symbol(),
+            ChannelKind::Accelerometer(accelerometer) => accelerometer.units.symbol(),
+        }
+    }
+
+    /// Input range used when none is configured: ±10 V, 0 to 100 °C, 0 to 20 mA, ±1000 µε,
+    /// ±2 mV/V or ±5 g
+    pub fn default_range(&self) -> Range {
+        let temperature = |units : TemperatureUnits| Range { min : units.from_celsius(0.0), max : units.from_celsius(100.0) };
+        let symmetric = |max : f64| Range { min : -max, max : max };
+        match self {
+            ChannelKind::Voltage => symmetric(10.0),
+            ChannelKind::Thermocouple(thermocouple) => temperature(thermocouple.units),
+            ChannelKind::Rtd(rtd) => temperature(rtd.units),
+            ChannelKind::Current(_) => Range { min : 0.0, max : 0.02 },
+            ChannelKind::Strain(_) => symmetric(0.001),
+            ChannelKind::Bridge(bridge) => symmetric(bridge.units.from_millivolts_per_volt(2.0)),
+            ChannelKind::Accelerometer(accelerometer) => symmetric(accelerometer.units.from_g(5.0)),
+        }
+    }
+
+    /// Whether the channel is created with a terminal configuration
+    pub fn has_terminal(&self) -> bool {
+        matches!(self, ChannelKind::Voltage | ChannelKind::Current(_) | ChannelKind::Accelerometer(_))
+    }
+}
+
+/// Parse a kind of channel written as `voltage`, `thermocouple[:TYPE]`, `rtd[:TYPE]`,
+/// `current`, `strain`, `bridge` or `accelerometer`, e.g. `thermocouple:j`, the other
+/// sensor settings take their defaults
+pub fn parse_kind(s : &str) -> Result<ChannelKind, String> {
+    let (kind, sensor) = match s.split_once(':') {
+        Some((kind, sensor)) => (kind.trim(), Some(sensor.trim())),
+        None => (s.trim(), None),
+    };
+    match (kind.to_ascii_lowercase().as_str(), sensor) {
+        ("voltage", None) => Ok(ChannelKind::Voltage),
+        ("thermocouple", sensor) => {
+            let thermocouple_type = match sensor {
+                Some(sensor) => ThermocoupleType::from_str(sensor, true).map_err(|_| format!("unknown thermocouple type: {}", sensor))?,
+                None => ThermocoupleType::K,
+            };
+            Ok(ChannelKind::Thermocouple(Thermocouple::new(thermocouple_type)))
+        }
+        ("rtd", sensor) => {
+            let rtd_type = match sensor {
+                Some(sensor) => RtdType::from_str(sensor, true).map_err(|_| format!("unknown RTD type: {}", sensor))?,
+                None => RtdType::Pt3851,
+            };
+            Ok(ChannelKind::Rtd(Rtd::new(rtd_type)))
+        }
+        ("current", None) => Ok(ChannelKind::Current(Current::default())),
+        ("strain", None) => Ok(ChannelKind::Strain(StrainGage::default())),
+        ("bridge", None) => Ok(ChannelKind::Bridge(Bridge::default())),
+        ("accelerometer", None) => Ok(ChannelKind::Accelerometer(Accelerometer::default())),
+        _ => Err(format!("expected voltage, thermocouple[:TYPE], rtd[:TYPE], current, strain, bridge or accelerometer, got {}", s)),
+    }
+}
+
+/// Unit of temperature channels
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnits {
+    /// Degrees Celsius
+    #[default]
+    DegC,
+    /// Degrees Fahrenheit
+    DegF,
+    Kelvin,
+    /// Degrees Rankine
+    DegR
+}
+
+impl TemperatureUnits {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            TemperatureUnits::DegC => "°C",
+            TemperatureUnits::DegF => "°F",
+            TemperatureUnits::Kelvin => "K",
+            TemperatureUnits::DegR => "°R",
+        }
+    }
+
+    /// Convert a temperature in degrees Celsius to these units
+    pub fn from_celsius(&self, celsius : f64) -> f64 {
+        match self {
+            TemperatureUnits::DegC => celsius,
+            TemperatureUnits::DegF => celsius*1.8 + 32.0,
+            TemperatureUnits::Kelvin => celsius + 273.15,
+            TemperatureUnits::DegR => (celsius + 273.15)*1.8,
+        }
+    }
+}
+
+/// Thermocouple type by letter designation
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThermocoupleType {
+    B, E, J, K, N, R, S, T
+}
+
+/// Where the cold-junction temperature of a thermocouple comes from
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CjcSource {
+    /// Sensor built into the module or terminal block
+    #[default]
+    BuiltIn,
+    /// Fixed temperature given by `cjc_value`
+    Constant,
+    /// Another channel given by `cjc_channel`
+    Channel
+}
+
+/// Thermocouple channel settings
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Thermocouple {
+    #[serde(rename = "type")]
+    pub thermocouple_type : ThermocoupleType,
+    #[serde(default)]
+    pub units : TemperatureUnits,
+    #[serde(default)]
+    pub cjc_source : CjcSource,
+    /// Cold-junction temperature in `units` if the source is `constant`
+    #[serde(default = "default_cjc_value")]
+    pub cjc_value : f64,
+    /// Channel measuring the cold-junction temperature if the source is `channel`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cjc_channel : Option<String>
+}
+
+fn default_cjc_value() -> f64 {
+    25.0
+}
+
+impl Thermocouple {
+    /// Thermocouple in °C with built-in cold-junction compensation
+    pub fn new(thermocouple_type : ThermocoupleType) -> Thermocouple {
+        Thermocouple {
+            thermocouple_type : thermocouple_type,
+            units : TemperatureUnits::DegC,
+            cjc_source : CjcSource::BuiltIn,
+            cjc_value : default_cjc_value(),
+            cjc_channel : None
+        }
+    }
+}
+
+/// Platinum RTD type by temperature coefficient
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RtdType {
+    /// α = 0.003750
+    Pt3750,
+    /// α = 0.003851, IEC 60751
+    Pt3851,
+    /// α = 0.003911
+    Pt3911,
+    /// α = 0.003916
+    Pt3916,
+    /// α = 0.003920
+    Pt3920,
+    /// α = 0.003928
+    Pt3928
+}
+
+/// Number of wires connecting a resistive sensor
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum Wiring {
+    #[serde(rename = "2-wire")]
+    TwoWire,
+    #[serde(rename = "3-wire")]
+    ThreeWire,
+    #[default]
+    #[serde(rename = "4-wire")]
+    FourWire
+}
+
+/// Source of a sensor's excitation
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExcitationSource {
+    /// Supplied by the module
+    #[default]
+    Internal,
+    /// Supplied by external circuitry
+    External,
+    None
+}
+
+/// RTD channel settings
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rtd {
+    #[serde(rename = "type")]
+    pub rtd_type : RtdType,
+    #[serde(default)]
+    pub units : TemperatureUnits,
+    #[serde(default)]
+    pub wiring : Wiring,
+    #[serde(default)]
+    pub excitation : ExcitationSource,
+    /// Excitation current [A]
+    #[serde(default = "default_excitation_current")]
+    pub current : f64,
+    /// Resistance at 0 °C [Ω]
+    #[serde(default = "default_r0")]
+    pub r0 : f64
+}
+
+fn default_excitation_current() -> f64 {
+    0.001
+}
+
+fn default_r0() -> f64 {
+    100.0
+}
+
+impl Rtd {
+    /// 100 Ω RTD in °C, 4-wire with 1 mA internal excitation
+    pub fn new(rtd_type : RtdType) -> Rtd {
+        Rtd {
+            rtd_type : rtd_type,
+            units : TemperatureUnits::DegC,
+            wiring : Wiring::FourWire,
+            excitation : ExcitationSource::Internal,
+            current : default_excitation_current(),
+            r0 : default_r0()
+        }
+    }
+}
+
+/// Location of the shunt resistor a current is measured across
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShuntLocation {
+    /// Whatever the module has, internal if it has one
+    #[default]
+    Default,
+    /// Built into the module
+    Internal,
+    /// Wired externally, with the resistance given by `shunt_resistance`
+    External
+}
+
+/// Current channel settings
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Current {
+    pub shunt : ShuntLocation,
+    /// Resistance of an external shunt [Ω]
+    pub shunt_resistance : f64
+}
+
+impl Default for Current {
+    fn default() -> Current {
+        Current {
+            shunt : ShuntLocation::Default,
+            shunt_resistance : 249.0
+        }
+    }
+}
+
+/// Strain gage bridge configuration, by NI's bridge type numbering
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrainConfig {
+    /// Four active gages, two in tension and two in compression
+    #[default]
+    FullBridgeI,
+    /// Four active gages, two aligned with the strain and two Poisson gages
+    FullBridgeII,
+    /// Four active gages, two aligned with the strain and two Poisson gages on opposite sides
+    FullBridgeIII,
+    /// Two active gages, one aligned with the strain and one Poisson gage
+    HalfBridgeI,
+    /// Two active gages, one in tension and one in compression
+    HalfBridgeII,
+    /// One active gage
+    QuarterBridgeI,
+    /// One active gage and one temperature compensating dummy gage
+    QuarterBridgeII
+}
+
+/// Strain gage channel settings
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StrainGage {
+    pub configuration : StrainConfig,
+    pub excitation : ExcitationSource,
+    /// Excitation voltage [V]
+    pub voltage : f64,
+    pub gage_factor : f64,
+    /// Gage resistance without strain [Ω]
+    pub nominal_resistance : f64,
+    pub poisson_ratio : f64,
+    /// Resistance of each lead wire [Ω]
+    pub lead_resistance : f64,
+    /// Bridge output without strain [V], subtracted before converting to strain
+    pub initial_bridge_voltage : f64
+}
+
+impl Default for StrainGage {
+    fn default() -> StrainGage {
+        StrainGage {
+            configuration : StrainConfig::FullBridgeI,
+            excitation : ExcitationSource::Internal,
+            voltage : 2.5,
+            gage_factor : 2.0,
+            nominal_resistance : 350.0,
+            poisson_ratio : 0.3,
+            lead_resistance : 0.0,
+            initial_bridge_voltage : 0.0
+        }
+    }
+}
+
+/// Number of active elements in a bridge
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BridgeConfig {
+    #[default]
+    Full,
+    Half,
+    Quarter
+}
+
+/// Unit of bridge channels, output voltage per excitation voltage
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum BridgeUnits {
+    #[default]
+    #[serde(rename = "mV/V")]
+    MillivoltsPerVolt,
+    #[serde(rename = "V/V")]
+    VoltsPerVolt
+}
+
+impl BridgeUnits {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BridgeUnits::MillivoltsPerVolt => "mV/V",
+            BridgeUnits::VoltsPerVolt => "V/V",
+        }
+    }
+
+    /// Convert a bridge output in mV/V to these units
+    pub fn from_millivolts_per_volt(&self, value : f64) -> f64 {
+        match self {
+            BridgeUnits::MillivoltsPerVolt => value,
+            BridgeUnits::VoltsPerVolt => value/1000.0,
+        }
+    }
+}
+
+/// Bridge channel settings
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bridge {
+    pub configuration : BridgeConfig,
+    pub units : BridgeUnits,
+    pub excitation : ExcitationSource,
+    /// Excitation voltage [V]
+    pub voltage : f64,
+    /// Bridge resistance without load [Ω]
+    pub nominal_resistance : f64
+}
+
+impl Default for Bridge {
+    fn default() -> Bridge {
+        Bridge {
+            configuration : BridgeConfig::Full,
+            units : BridgeUnits::MillivoltsPerVolt,
+            excitation : ExcitationSource::Internal,
+            voltage : 2.5,
+            nominal_resistance : 350.0
+        }
+    }
+}
+
+/// Unit of acceleration channels
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum AccelerationUnits {
+    /// Standard gravity
+    #[default]
+    #[serde(rename = "g")]
+    G,
+    #[serde(rename = "m/s2")]
+    MetersPerSecondSquared,
+    #[serde(rename = "in/s2")]
+    InchesPerSecondSquared
+}
+
+impl AccelerationUnits {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            AccelerationUnits::G => "g",
+            AccelerationUnits::MetersPerSecondSquared => "m/s²",
+            AccelerationUnits::InchesPerSecondSquared => "in/s²",
+        }
+    }
+
+    /// Convert an acceleration in g to these units
+    pub fn from_g(&self, g : f64) -> f64 {
+        match self {
+            AccelerationUnits::G => g,
+            AccelerationUnits::MetersPerSecondSquared => g*9.80665,
+            AccelerationUnits::InchesPerSecondSquared => g*9.80665/0.0254,
+        }
+    }
+}
+
+/// Unit of accelerometer sensitivity
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum SensitivityUnits {
+    #[default]
+    #[serde(rename = "mV/g")]
+    MillivoltsPerG,
+    #[serde(rename = "V/g")]
+    VoltsPerG
+}
+
+/// IEPE accelerometer channel settings
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Accelerometer {
+    pub units : AccelerationUnits,
+    /// Sensitivity from the sensor's calibration sheet
+    pub sensitivity : f64,
+    pub sensitivity_units : SensitivityUnits,
+    pub excitation : ExcitationSource,
+    /// IEPE excitation current [A]
+    pub current : f64
+}
+
+impl Default for Accelerometer {
+    fn default() -> Accelerometer {
+        Accelerometer {
+            units : AccelerationUnits::G,
+            sensitivity : 100.0,
+            sensitivity_units : SensitivityUnits::MillivoltsPerG,
+            excitation : ExcitationSource::Internal,
+            current : 0.002
+        }
+    }
+}
+
+/// Input range, written as `MIN:MAX` in configuration files
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Range {
+    pub min : f64,
+    pub max : f64
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.min, self.max)
+    }
+}
+
+impl TryFrom<String> for Range {
+    type Error = String;
+
+    fn try_from(s : String) -> Result<Range, String> {
+        parse_range(&s)
+    }
+}
+
+impl From<Range> for String {
+    fn from(range : Range) -> String {
+        range.to_string()
+    }
+}
+
+impl Range {
+    /// Whether `other` lies within this range
+    pub fn contains(&self, other : &Range) -> bool {
+        self.min <= other.min && other.max <= self.max
+    }
+}
+
+/// Parse an input range written as `MIN:MAX`, e.g. `-5:5`
+pub fn parse_range(s : &str) -> Result<Range, String> {
+    let (min, max) = s.split_once(':').ok_or_else(|| format!("expected MIN:MAX, got {}", s))?;
+    let min : f64 = min.trim().parse().map_err(|_| format!("invalid minimum: {}", min))?;
+    let max : f64 = max.trim().parse().map_err(|_| format!("invalid maximum: {}", max))?;
+    if min.partial_cmp(&max) != Some(Ordering::Less) {
+        return Err(format!("minimum must be less than maximum: {}", s));
+    }
+    Ok(Range { min : min, max : max })
+}
+
+/// Split a channel into its prefix and number, e.g. `ai` and 12 for `ai12`
+fn channel_number(channel : &str) -> Option<(&str, u32)> {
+    let prefix = channel.trim_end_matches(|c : char| c.is_ascii_digit());
+    let number = channel[prefix.len()..].parse().ok()?;
+    Some((prefix, number))
+}
+
+/// Expand a list of physical channels such as `Dev1/ai0:3, Dev2/ai1` into single physical
+/// channels in list order. Ranges include both ends and count down if the last number is
+/// the smaller one, the end may repeat the prefix as in `Dev1/ai0:ai3`
+pub fn expand_physical_channels(list : &str) -> Result<Vec<String>, String> {
+    let mut channels = Vec::new();
+    for item in list.split(',').map(str::trim) {
+        if item.is_empty() {
+            return Err(format!("empty entry in channel list {}", list));
+        }
+        let (device, channel) = match item.rsplit_once('/') {
+            Some((device, channel)) if !device.trim_start_matches('/').is_empty() && !channel.is_empty() => (device, channel),
+            _ => return Err(format!("expected DEVICE/CHANNEL, got {}", item)),
+        };
+        let (first, last) = match channel.split_once(':') {
+            Some(range) => range,
+            None => {
+                channels.push(String::from(item));
+                continue;
+            }
+        };
+
+        let invalid = || format!("invalid channel range {}, expected e.g. {}/ai0:3", item, device);
+        let (prefix, first) = channel_number(first.trim()).ok_or_else(invalid)?;
+        let (last_prefix, last) = channel_number(last.trim()).ok_or_else(invalid)?;
+        if !last_prefix.is_empty() && last_prefix != prefix {
+            return Err(invalid());
+        }
+        let numbers : Vec<u32> = if first <= last { (first..=last).collect() } else { (last..=first).rev().collect() };
+        channels.extend(numbers.into_iter().map(|number| format!("{}/{}{}", device, prefix, number)));
+    }
+    Ok(channels)
+}
+
+/// Parse a channel list of physical channels and ranges as in `expand_physical_channels`,
+/// each optionally named for the output with `NAME=`, e.g. `supply=Dev1/ai0, Dev1/ai4:7`.
+/// Every item becomes an entry with default settings
+pub fn parse_channel_list(list : &str) -> Result<Vec<ChannelConfig>, String> {
+    let mut entries = Vec::new();
+    for item in list.split(',').map(str::trim) {
+        if item.is_empty() {
+            return Err(format!("empty entry in channel list {}", list));
+        }
+        let (name, physical) = match item.split_once('=') {
+            Some((name, _)) if name.trim().is_empty() => return Err(format!("missing channel name before = in {}", item)),
+            Some((name, physical)) => (Some(String::from(name.trim())), physical.trim()),
+            None => (None, item),
+        };
+        entries.push(ChannelConfig {
+            physical : String::from(physical),
+            name : name,
+            range : None,
+            terminal : MeasurementMode::default(),
+            kind : ChannelKind::Voltage,
+            scale : None
+        });
+    }
+    Ok(entries)
+}
+
+/// Expand entries to one per physical channel and check that no physical channel or output
+/// name is used twice. Physical channels are compared ignoring case, as DAQmx does
+pub fn expand_channels(entries : &[ChannelConfig]) -> Result<Vec<ChannelConfig>, String> {
+    let mut channels = Vec::new();
+    for entry in entries {
+        channels.extend(entry.expand()?);
+    }
+    let mut physical = HashSet::new();
+    let mut names = HashSet::new();
+    for channel in &channels {
+        if !physical.insert(channel.physical.trim_start_matches('/').to_lowercase()) {
+            return Err(format!("{} appears more than once in the channel list", channel.physical));
+        }
+        let name = channel.name.as_deref().unwrap_or(&channel.physical);
+        if !names.insert(name) {
+            return Err(format!("channel name {} is used more than once", name));
+        }
+    }
+    Ok(channels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn physical(channels : &[ChannelConfig]) -> Vec<&str> {
+        channels.iter().map(|channel| channel.physical.as_str()).collect()
+    }
+
+    fn names(channels : &[ChannelConfig]) -> Vec<Option<&str>> {
+        channels.iter().map(|channel| channel.name.as_deref()).collect()
+    }
+
+    #[test]
+    fn ascending_range() {
+        assert_eq!(expand_physical_channels("Dev1/ai0:3").unwrap(), ["Dev1/ai0", "Dev1/ai1", "Dev1/ai2", "Dev1/ai3"]);
+    }
+
+    #[test]
+    fn descending_range() {
+        assert_eq!(expand_physical_channels("Dev1/ai3:1").unwrap(), ["Dev1/ai3", "Dev1/ai2", "Dev1/ai1"]);
+    }
+
+    #[test]
+    fn range_repeating_prefix() {
+        assert_eq!(expand_physical_channels("Dev1/ai0:ai2").unwrap(), ["Dev1/ai0", "Dev1/ai1", "Dev1/ai2"]);
+        assert!(expand_physical_channels("Dev1/ai0:ao2").is_err());
+    }
+
+    #[test]
+    fn multi_device_list() {
+        assert_eq!(expand_physical_channels("Dev1/ai0:1, cDAQ1Mod2/ai4, Dev2/ai7").unwrap(),
+            ["Dev1/ai0", "Dev1/ai1", "cDAQ1Mod2/ai4", "Dev2/ai7"]);
+    }
+
+    #[test]
+    fn invalid_lists() {
+        assert!(expand_physical_channels("Dev1/ai0,,Dev1/ai1").is_err());
+        assert!(expand_physical_channels("ai0").is_err());
+        assert!(expand_physical_channels("Dev1/aiX:3").is_err());
+    }
+
+    #[test]
+    fn alias_numbering() {
+        let entries = parse_channel_list("supply=Dev1/ai0, bridge=Dev1/ai4:6, Dev2/ai1").unwrap();
+        let channels = expand_channels(&entries).unwrap();
+        assert_eq!(physical(&channels), ["Dev1/ai0", "Dev1/ai4", "Dev1/ai5", "Dev1/ai6", "Dev2/ai1"]);
+        assert_eq!(names(&channels), [Some("supply"), Some("bridge0"), Some("bridge1"), Some("bridge2"), None]);
+    }
+
+    #[test]
+    fn missing_alias() {
+        assert!(parse_channel_list("=Dev1/ai0").is_err());
+    }
+
+    #[test]
+    fn duplicate_physical_channel() {
+        let entries = parse_channel_list("Dev1/ai0:2, /dev1/AI1").unwrap();
+        assert!(expand_channels(&entries).is_err());
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let entries = parse_channel_list("a=Dev1/ai0, a=Dev1/ai1").unwrap();
+        assert!(expand_channels(&entries).is_err());
+        // A name numbered from an alias clashes as well
+        let entries = parse_channel_list("a=Dev1/ai0:1, a1=Dev2/ai0").unwrap();
+        assert!(expand_channels(&entries).is_err());
+    }
+}
@@ -0,0 +1,413 @@
+//! Command line options and how they map onto the acquisition configuration
+
+use std::path::{Path, PathBuf};
+
+use chrono::prelude::*;
+use clap::{Parser, Subcommand};
+
+use daqlogger::{Backend, Config, MeasurementMode};
+use daqlogger::backend::{SampleMode, Signal};
+use daqlogger::channel::{ChannelKind, Range, expand_channels, parse_channel_list, parse_kind, parse_range};
+use daqlogger::config::{ConfigFormat, OutputConfig};
+use daqlogger::queue::OverflowPolicy;
+use daqlogger::schedule::{Schedule, parse_duration, parse_time};
+use daqlogger::sink::{Capture, Interval, OutputFormat, TimestampFormat, parse_size};
+use daqlogger::trigger::{ReferenceTrigger, SoftwareTrigger, Trigger, parse_software_trigger, parse_trigger};
+
+/// Log analog input samples from NI-DAQmx devices to CSV, TDMS, Arrow and Parquet files
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Acquire samples and write them to the outputs
+    Acquire(Box<AcquireArgs>),
+    /// List devices, their analog input channels, terminal configurations and voltage ranges
+    List(ListArgs),
+    /// Play a recording back through the outputs at the pace it was acquired
+    Replay(ReplayArgs),
+    /// Convert a recording to another format, or split it into files or events
+    Convert(ConvertArgs),
+    /// Check a short acquisition and every file format, on the simulated device unless --backend is given
+    ///
+    /// Without channels the first two analog inputs of the first device that has some are tested.
+    Selftest(SelftestArgs),
+    /// Show the latest value, minimum, mean and maximum of every channel while acquiring continuously
+    Monitor(MonitorArgs),
+}
+
+/// Channels, timing and triggers of the task, shared by the subcommands that acquire
+#[derive(clap::Args, Clone, Debug)]
+pub struct TaskArgs {
+    /// The names of the physical channels to use to create virtual channels. You can specify a list or range of physical channels,
+    /// and name a channel or range for the output with NAME=. Replaces the channels of the configuration file.
+    ///
+    /// SYNTAX: [<name>=]<device>/<channel>[:<last>], ...
+    ///
+    /// EXAMPLE: supply=cDAQ9181-1FE3677Mod1/ai0, cDAQ9181-1FE3677Mod1/ai8:11
+    pub channels: Option<String>,
+    #[arg(value_enum)]
+    /// Terminal configuration mode of channels without one in --terminal [default: rse]
+    pub mode: Option<MeasurementMode>,
+    /// Acquisition configuration file (TOML, or YAML with a .yaml/.yml extension), command line options override its settings
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+    /// Terminal configuration of each channel in the channel list, overriding the mode
+    ///
+    /// EXAMPLE: --terminal diff,rse
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub terminal: Vec<MeasurementMode>,
+    /// What each channel measures, either one for all channels or one per channel in the channel list:
+    /// voltage, thermocouple[:TYPE] (type J, K, ...), rtd[:TYPE] (pt3851, ...), current, strain, bridge or accelerometer.
+    /// Other sensor settings need a configuration file
+    ///
+    /// EXAMPLE: --kind voltage,thermocouple:j
+    #[arg(long, value_parser = parse_kind, value_delimiter = ',')]
+    pub kind: Vec<ChannelKind>,
+    /// Input range MIN:MAX in the units of the channel, either one for all channels or one per channel in the channel list [default depends on the kind, e.g. -10:10 V]
+    ///
+    /// EXAMPLE: --range=-1:1,-10:10
+    #[arg(long, value_parser = parse_range, value_delimiter = ',', allow_hyphen_values = true)]
+    pub range: Vec<Range>,
+    /// Sample rate [samples/sec] [default: 1000]
+    #[arg(short, long)]
+    pub rate: Option<f64>,
+    /// Number of samples to take for each measurement batch [N] [default: 1000]
+    #[arg(short, long)]
+    pub size: Option<u64>,
+    /// Acquire continuously instead of starting and stopping the task for every batch
+    #[arg(short, long)]
+    pub continuous: bool,
+    /// Take the time of the first sample from the device instead of the system clock at task start (cDAQ/TSN devices)
+    #[arg(long)]
+    pub first_sample_timestamp: bool,
+    /// Wait for a trigger before acquiring: digital-edge:SOURCE[:rising|falling], analog-edge:SOURCE:LEVEL[:rising|falling]
+    /// or analog-window:SOURCE:BOTTOM:TOP[:entering|leaving]. Analog triggers take a channel of the task or an analog trigger input as source
+    ///
+    /// EXAMPLE: --start-trigger analog-edge:cDAQ9181-1FE3677Mod1/ai0:2.5
+    #[arg(long, value_parser = parse_trigger, allow_hyphen_values = true)]
+    pub start_trigger: Option<Trigger>,
+    /// Trigger each finite batch is taken around, with --pretrigger samples before it. Same syntax as --start-trigger
+    #[arg(long, value_parser = parse_trigger, allow_hyphen_values = true)]
+    pub reference_trigger: Option<Trigger>,
+    /// Samples per channel kept ahead of the reference trigger [N] [default: 0]
+    #[arg(long)]
+    pub pretrigger: Option<u64>,
+    /// Acquisition backend [default: nidaqmx]
+    #[arg(short, long, value_enum)]
+    pub backend: Option<Backend>,
+    /// Signals generated by the simulated backend, assigned to channels in turn [default: sine]
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub sim_signals: Vec<Signal>,
+}
+
+/// When the acquisition starts and stops
+#[derive(clap::Args, Debug)]
+pub struct ScheduleArgs {
+    /// Wait until this time before starting: a date and time (2025-04-01 08:00:00, RFC 3339 with an offset) or the next occurrence of a time of day (08:00)
+    #[arg(long, value_parser = parse_time)]
+    pub start_at: Option<DateTime<Local>>,
+    /// Stop after this long since the first sample: seconds, or with units such as 10m, 1h30m, 2d
+    #[arg(long, value_parser = parse_duration)]
+    pub duration: Option<f64>,
+    /// Stop after this many samples per channel [N]
+    #[arg(long)]
+    pub total_samples: Option<u64>,
+    /// Stop at this time, same syntax as --start-at
+    #[arg(long, value_parser = parse_time)]
+    pub until: Option<DateTime<Local>>,
+}
+
+/// Where and how samples are written
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// Output file, "-" for stdout. File names are chrono format patterns expanded with the time of the first sample in the file, e.g. rig1_%Y%m%d_%H%M%S.csv [default: -]
+    ///
+    /// Output options apply to the first output of the configuration file.
+    #[arg(short, long)]
+    pub output: Option<String>,
+    /// Start a new file when the current one reaches this size [bytes], K, M and G suffixes allowed
+    #[arg(long, value_parser = parse_size)]
+    pub rotate_size: Option<u64>,
+    /// Start a new file every hour or day
+    #[arg(long, value_enum)]
+    pub rotate_interval: Option<Interval>,
+    /// Start a new file after this many samples per channel [N]
+    #[arg(long)]
+    pub rotate_samples: Option<u64>,
+    /// Number of most recent output files to keep, older ones are deleted [N]
+    #[arg(long)]
+    pub keep: Option<usize>,
+    /// Capture only the scans around events on a channel, each event to a numbered file, during continuous acquisition:
+    /// level:CHANNEL:LEVEL[:above|below], edge:CHANNEL:LEVEL[:rising|falling] or window:CHANNEL:BOTTOM:TOP[:entering|leaving].
+    /// CHANNEL is a channel name or the physical channel of an unnamed one
+    ///
+    /// EXAMPLE: --capture edge:cDAQ9181-1FE3677Mod1/ai0:2.5 -o event_%H%M%S.csv
+    #[arg(long, value_parser = parse_software_trigger, allow_hyphen_values = true)]
+    pub capture: Option<SoftwareTrigger>,
+    /// Scans captured ahead of each event [N] [default: 0]
+    #[arg(long)]
+    pub capture_pretrigger: Option<u64>,
+    /// Scans captured from each event on [N] [default: 1000]
+    #[arg(long)]
+    pub capture_posttrigger: Option<u64>,
+    /// Time after a capture during which events are ignored [s] [default: 0]
+    #[arg(long)]
+    pub holdoff: Option<f64>,
+    /// Stop after capturing this many events [N]
+    #[arg(long)]
+    pub max_events: Option<u64>,
+    /// Output file format [default: from the extension of --output, csv otherwise]
+    #[arg(short, long, value_enum)]
+    pub format: Option<OutputFormat>,
+    /// Name of the TDMS group the channels are written to [default: Data]
+    #[arg(long)]
+    pub tdms_group: Option<String>,
+    /// Timestamp column format [default: iso8601]
+    #[arg(long, value_enum)]
+    pub timestamp: Option<TimestampFormat>,
+    /// Column delimiter [default: ,]
+    #[arg(long)]
+    pub delimiter: Option<char>,
+    /// Digits after the decimal point of sample values [N], shortest exact representation if not set
+    #[arg(long)]
+    pub precision: Option<usize>,
+    /// Record the time of the first sample, sample rate, delimiter and input ranges in # comment lines above the CSV header
+    #[arg(long)]
+    pub csv_metadata: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AcquireArgs {
+    #[command(flatten)]
+    pub task: TaskArgs,
+    #[command(flatten)]
+    pub schedule: ScheduleArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Batches waiting for each output's writer at most [N] [default: 16]
+    #[arg(long)]
+    pub queue_size: Option<usize>,
+    /// What to do when an output's writer falls behind and its queue is full [default: block]
+    #[arg(long, value_enum)]
+    pub overflow: Option<OverflowPolicy>,
+    /// Print the effective configuration and exit
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "toml")]
+    pub dump_config: Option<ConfigFormat>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ListArgs {
+    /// Backend whose devices are listed
+    #[arg(short, long, value_enum, default_value = "nidaqmx")]
+    pub backend: Backend,
+    /// Print JSON instead of an indented list
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ReplayArgs {
+    /// Recording written by daqlogger: a .csv, .tdms, .arrow or .parquet file, "-" for CSV on stdin
+    pub input: PathBuf,
+    /// Column delimiter of CSV input without a # delimiter line
+    #[arg(long, default_value_t = ',')]
+    pub input_delimiter: char,
+    /// Playback speed relative to the acquisition, 0 for as fast as possible
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConvertArgs {
+    /// Recording written by daqlogger: a .csv, .tdms, .arrow or .parquet file, "-" for CSV on stdin
+    pub input: PathBuf,
+    /// Column delimiter of CSV input without a # delimiter line
+    #[arg(long, default_value_t = ',')]
+    pub input_delimiter: char,
+    #[command(flatten)]
+    pub output: OutputArgs,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SelftestArgs {
+    #[command(flatten)]
+    pub task: TaskArgs,
+    /// Batches to acquire [N]
+    #[arg(long, default_value_t = 3)]
+    pub batches: u64,
+    /// Directory the files of the format checks are written to and kept in [default: a temporary directory, removed afterwards]
+    #[arg(long)]
+    pub keep_files: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct MonitorArgs {
+    #[command(flatten)]
+    pub task: TaskArgs,
+    /// Time between refreshes [s]
+    #[arg(long, default_value_t = 1.0)]
+    pub interval: f64,
+}
+
+/// Load the configuration file, if any, and override its channel, timing and trigger settings
+/// with the command line options
+fn configure(args : &TaskArgs) -> Result<Config, String> {
+    let mut config = match &args.config {
+        Some(path) => Config::load(path)?,
+        None => Config::default(),
+    };
+
+    if let Some(backend) = args.backend {
+        config.backend = backend;
+    }
+    if !args.sim_signals.is_empty() {
+        config.sim_signals = args.sim_signals.clone();
+    }
+
+    // A channel list on the command line replaces the configured channels
+    if let Some(channels) = &args.channels {
+        config.channels = parse_channel_list(channels).map_err(|err| format!("Invalid channel list: {}", err))?;
+    }
+    if config.channels.is_empty() {
+        return Err(String::from("No channels given, either on the command line or in the configuration file"));
+    }
+    // Per-channel options apply to physical channels, not to the ranges that name several
+    config.channels = expand_channels(&config.channels).map_err(|err| format!("Invalid channel list: {}", err))?;
+    let channels = &mut config.channels;
+    // One range applies to every entry, otherwise there must be one per entry
+    match args.range.len() {
+        0 => {}
+        1 => channels.iter_mut().for_each(|channel| channel.range = Some(args.range[0])),
+        n if n == channels.len() => channels.iter_mut().zip(&args.range).for_each(|(channel, range)| channel.range = Some(*range)),
+        n => return Err(format!("{} input ranges given for {} channels", n, channels.len())),
+    }
+    match args.kind.len() {
+        0 => {}
+        1 => channels.iter_mut().for_each(|channel| channel.kind = args.kind[0].clone()),
+        n if n == channels.len() => channels.iter_mut().zip(&args.kind).for_each(|(channel, kind)| channel.kind = kind.clone()),
+        n => return Err(format!("{} channel kinds given for {} channels", n, channels.len())),
+    }
+    if let Some(mode) = args.mode {
+        channels.iter_mut().for_each(|channel| channel.terminal = mode);
+    }
+    match args.terminal.len() {
+        0 => {}
+        n if n == channels.len() => channels.iter_mut().zip(&args.terminal).for_each(|(channel, terminal)| channel.terminal = *terminal),
+        n => return Err(format!("{} terminal configurations given for {} channels", n, channels.len())),
+    }
+
+    let timing = &mut config.timing;
+    if let Some(rate) = args.rate {
+        timing.rate = rate;
+    }
+    if let Some(size) = args.size {
+        timing.size = size;
+    }
+    if args.continuous {
+        timing.mode = SampleMode::Continuous;
+    }
+    if args.first_sample_timestamp {
+        timing.first_sample_timestamp = true;
+    }
+
+    let triggers = &mut config.trigger;
+    if let Some(trigger) = &args.start_trigger {
+        triggers.start = Some(trigger.clone());
+    }
+    if let Some(trigger) = &args.reference_trigger {
+        let pretrigger_samples = triggers.reference.as_ref().map_or(0, |reference| reference.pretrigger_samples);
+        triggers.reference = Some(ReferenceTrigger { trigger : trigger.clone(), pretrigger_samples : pretrigger_samples });
+    }
+    if let Some(pretrigger) = args.pretrigger {
+        match &mut triggers.reference {
+            Some(reference) => reference.pretrigger_samples = pretrigger,
+            None => return Err(String::from("--pretrigger requires a reference trigger")),
+        }
+    }
+    Ok(config)
+}
+
+/// Override the configured schedule with the command line options
+fn configure_schedule(schedule : &mut Schedule, args : &ScheduleArgs) {
+    schedule.start_at = args.start_at.or(schedule.start_at);
+    schedule.duration = args.duration.or(schedule.duration);
+    schedule.total_samples = args.total_samples.or(schedule.total_samples);
+    schedule.until = args.until.or(schedule.until);
+}
+
+/// Override the settings of the first output with the command line options
+pub fn configure_output(outputs : &mut Vec<OutputConfig>, args : &OutputArgs) -> Result<(), String> {
+    if outputs.is_empty() {
+        outputs.push(OutputConfig::default());
+    }
+    let output = &mut outputs[0];
+    if let Some(path) = &args.output {
+        output.path = path.clone();
+    }
+    if let Some(format) = args.format.or_else(|| args.output.as_deref().and_then(|path| OutputFormat::of(Path::new(path)))) {
+        output.format = format;
+    }
+    if let Some(tdms_group) = &args.tdms_group {
+        output.tdms_group = tdms_group.clone();
+    }
+    if let Some(timestamp) = args.timestamp {
+        output.timestamp = timestamp;
+    }
+    if let Some(delimiter) = args.delimiter {
+        output.delimiter = delimiter;
+    }
+    output.precision = args.precision.or(output.precision);
+    output.csv_metadata |= args.csv_metadata;
+    output.rotate_size = args.rotate_size.or(output.rotate_size);
+    output.rotate_interval = args.rotate_interval.or(output.rotate_interval);
+    output.rotate_samples = args.rotate_samples.or(output.rotate_samples);
+    output.keep = args.keep.or(output.keep);
+
+    if let Some(trigger) = &args.capture {
+        match &mut output.capture {
+            Some(capture) => capture.trigger = trigger.clone(),
+            None => output.capture = Some(Capture::new(trigger.clone())),
+        }
+    }
+    if args.capture_pretrigger.is_some() || args.capture_posttrigger.is_some() || args.holdoff.is_some() || args.max_events.is_some() {
+        let capture = match &mut output.capture {
+            Some(capture) => capture,
+            None => return Err(String::from("Capture options require a software trigger (--capture)")),
+        };
+        capture.pretrigger = args.capture_pretrigger.unwrap_or(capture.pretrigger);
+        capture.posttrigger = args.capture_posttrigger.unwrap_or(capture.posttrigger);
+        capture.holdoff = args.holdoff.unwrap_or(capture.holdoff);
+        capture.max_events = args.max_events.or(capture.max_events);
+    }
+    Ok(())
+}
+
+/// Configuration of the acquire subcommand: the task, its schedule, outputs and queues
+pub fn configure_acquisition(args : &AcquireArgs) -> Result<Config, String> {
+    let mut config = configure(&args.task)?;
+    configure_schedule(&mut config.schedule, &args.schedule);
+    configure_output(&mut config.outputs, &args.output)?;
+
+    if let Some(capacity) = args.queue_size {
+        config.queue.capacity = capacity;
+    }
+    if let Some(overflow) = args.overflow {
+        config.queue.overflow = overflow;
+    }
+    config.validate()?;
+    Ok(config)
+}
+
+/// Configuration of a subcommand that acquires without outputs of its own: the task alone
+pub fn configure_task(args : &TaskArgs) -> Result<Config, String> {
+    let config = configure(args)?;
+    config.validate()?;
+    Ok(config)
+}
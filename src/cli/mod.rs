@@ -0,0 +1,5 @@
+//! Subcommands of the command line tool built on the library
+
+pub mod args;
+pub mod monitor;
+pub mod selftest;
@@ -0,0 +1,111 @@
+//! Live view of the channels of a running acquisition
+
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::AtomicUsize;
+use std::time::{Duration, Instant};
+
+use chrono::prelude::*;
+
+use daqlogger::DAQVTask;
+use daqlogger::sink::ChannelInfo;
+
+/// Samples of one channel since the last refresh
+#[derive(Copy, Clone)]
+struct Statistics {
+    last : f64,
+    min : f64,
+    max : f64,
+    sum : f64,
+    count : u64
+}
+
+impl Statistics {
+    fn new() -> Statistics {
+        Statistics {
+            last : f64::NAN,
+            min : f64::INFINITY,
+            max : f64::NEG_INFINITY,
+            sum : 0.0,
+            count : 0
+        }
+    }
+
+    fn add(&mut self, value : f64) {
+        self.last = value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn mean(&self) -> f64 {
+        self.sum/self.count as f64
+    }
+}
+
+/// Write one line per channel with its latest value and the minimum, mean and maximum since
+/// the last refresh. On a terminal the screen is cleared first so the table stays in place
+fn print_table(out : &mut dyn Write, clear : bool, time : DateTime<Local>, channels : &[ChannelInfo], statistics : &[Statistics]) -> io::Result<()> {
+    if clear {
+        write!(out, "\x1b[H\x1b[2J")?;
+    }
+    writeln!(out, "{}", time.format("%Y-%m-%d %H:%M:%S%.3f"))?;
+    let width = channels.iter().map(|channel| channel.name.len()).chain(std::iter::once("channel".len())).max().unwrap_or(0);
+    writeln!(out, "{:width$}  {:>12}  {:>12}  {:>12}  {:>12}  units", "channel", "last", "min", "mean", "max", width = width)?;
+    for (channel, statistics) in channels.iter().zip(statistics) {
+        writeln!(out, "{:width$}  {:>12.6}  {:>12.6}  {:>12.6}  {:>12.6}  {}", channel.name,
+            statistics.last, statistics.min, statistics.mean(), statistics.max, channel.units, width = width)?;
+    }
+    if !clear {
+        writeln!(out)?;
+    }
+    out.flush()
+}
+
+/// Acquire until a signal arrives, showing the channels every `interval` seconds
+///
+/// The table is refreshed after a batch has been read, so batches longer than the interval
+/// refresh it once per batch. Monitoring goes on after a buffer overrun and fails on any other
+/// read error.
+pub fn run(mut task : DAQVTask, interval : f64, signal : &AtomicUsize) -> Result<(), String> {
+    let mut out = io::stdout().lock();
+    let clear = out.is_terminal();
+    let channels = task.channel_info().to_vec();
+    let interval = Duration::from_secs_f64(interval);
+    let mut statistics = vec![Statistics::new(); channels.len()];
+    let mut refresh = Instant::now() + interval;
+    let mut result = Ok(());
+    for batch in task.batches().until(signal) {
+        let batch = match batch {
+            Ok(batch) => batch,
+            Err(err) if err.is_overrun() => {
+                eprintln!("{}", err);
+                continue;
+            }
+            Err(err) => {
+                result = Err(err.to_string());
+                break;
+            }
+        };
+        let batch = batch.as_batch();
+        for (statistics, (_, samples)) in statistics.iter_mut().zip(batch.columns()) {
+            samples.iter().for_each(|value| statistics.add(*value));
+        }
+        if let (Some(time), true) = (batch.last_timestamp(), Instant::now() >= refresh) {
+            print_table(&mut out, clear, time, &channels, &statistics).map_err(|err| err.to_string())?;
+            statistics.fill(Statistics::new());
+            refresh = Instant::now() + interval;
+        }
+    }
+    let stopped = task.stop().map_err(|err| err.to_string());
+    for warning in task.take_warnings() {
+        eprintln!("{}", warning);
+    }
+    for err in task.take_errors() {
+        eprintln!("{}", err);
+    }
+    if task.overruns() > 0 {
+        eprintln!("{} buffer overruns, {} samples per channel dropped", task.overruns(), task.samples_dropped());
+    }
+    result.and(stopped)
+}
@@ -0,0 +1,153 @@
+//! Checks of a short acquisition and of writing it to and reading it back from every file
+//! format
+
+use std::io;
+use std::path::Path;
+
+use chrono::prelude::*;
+use chrono::TimeDelta;
+
+use daqlogger::DAQVTask;
+use daqlogger::sink::{ArrowSink, CsvFormat, CsvSink, ParquetSink, TdmsSink, TimestampFormat};
+use daqlogger::sink::{OutputFormat, Sink, StreamInfo, open_recording};
+
+/// Largest difference between a timestamp and the one read back, CSV keeps microseconds
+const TIMESTAMP_TOLERANCE : TimeDelta = TimeDelta::microseconds(1);
+
+/// Read `batches` batches and check that every one is complete
+fn acquire(task : &mut DAQVTask, batches : u64, timestamps : &mut Vec<DateTime<Local>>, samples : &mut Vec<f64>) -> Result<(), String> {
+    let sample_count = task.sample_count();
+    for (index, batch) in task.batches().take(batches as usize).enumerate() {
+        let batch = batch.map_err(|err| err.to_string())?;
+        let batch = batch.as_batch();
+        if batch.len() as u64 != sample_count {
+            return Err(format!("batch {} has {} scans instead of {}", index, batch.len(), sample_count));
+        }
+        timestamps.extend_from_slice(batch.timestamps());
+        samples.extend_from_slice(batch.samples());
+    }
+    Ok(())
+}
+
+/// Timestamps increase, and within a batch they are one sample period apart
+fn check_timestamps(info : &StreamInfo, timestamps : &[DateTime<Local>]) -> Result<(), String> {
+    let period = 1e9/info.sample_rate;
+    for (batch, scans) in timestamps.chunks(info.batch_size as usize).enumerate() {
+        for (i, pair) in scans.windows(2).enumerate() {
+            let spacing = (pair[1] - pair[0]).num_nanoseconds().unwrap_or(i64::MAX) as f64;
+            if (spacing - period).abs() > 1.0 {
+                return Err(format!("scans {} and {} of batch {} are {} ns apart instead of {} ns", i, i + 1, batch, spacing, period));
+            }
+        }
+    }
+    if let Some(i) = timestamps.windows(2).position(|pair| pair[1] <= pair[0]) {
+        return Err(format!("timestamp of scan {} is not after the one before: {} then {}", i + 1, timestamps[i], timestamps[i + 1]));
+    }
+    Ok(())
+}
+
+/// Every sample is a number within the input range of its channel
+fn check_ranges(info : &StreamInfo, samples : &[f64]) -> Result<(), String> {
+    for (i, value) in samples.iter().enumerate() {
+        let channel = &info.channels[i % info.channels.len()];
+        if !(channel.min <= *value && *value <= channel.max) {
+            return Err(format!("{}: sample {} of scan {} is outside the input range {}:{} {}",
+                channel.name, value, i/info.channels.len(), channel.min, channel.max, channel.units));
+        }
+    }
+    Ok(())
+}
+
+/// Write the scans to `path` in `format` and compare what is read back with them
+fn round_trip(format : OutputFormat, path : &Path, info : &StreamInfo, timestamps : &[DateTime<Local>], samples : &[f64]) -> Result<(), String> {
+    let write = || -> io::Result<()> {
+        let mut sink : Box<dyn Sink> = match format {
+            OutputFormat::Csv => Box::new(CsvSink::create(path, CsvFormat { timestamp : TimestampFormat::ISO8601, delimiter : ',', precision : None, metadata : true })?),
+            OutputFormat::Tdms => Box::new(TdmsSink::create(path, "Data")?),
+            OutputFormat::Arrow => Box::new(ArrowSink::create(path)?),
+            OutputFormat::Parquet => Box::new(ParquetSink::create(path)?),
+        };
+        sink.begin(info)?;
+        let scans = info.batch_size as usize;
+        for (timestamps, samples) in timestamps.chunks(scans).zip(samples.chunks(scans*info.channels.len())) {
+            sink.write_batch(timestamps, samples)?;
+        }
+        sink.finish()
+    };
+    write().map_err(|err| format!("writing {}: {}", path.display(), err))?;
+
+    let mut recording = open_recording(path, ',').map_err(|err| format!("reading {}: {}", path.display(), err))?;
+    let read_info = recording.info().clone();
+    let names : Vec<&str> = info.channels.iter().map(|channel| channel.name.as_str()).collect();
+    let read_names : Vec<&str> = read_info.channels.iter().map(|channel| channel.name.as_str()).collect();
+    if read_names != names {
+        return Err(format!("channels read back as {} instead of {}", read_names.join(", "), names.join(", ")));
+    }
+    if read_info.sample_rate != info.sample_rate {
+        return Err(format!("sample rate read back as {} instead of {}", read_info.sample_rate, info.sample_rate));
+    }
+    if (read_info.epoch - info.epoch).abs() > TIMESTAMP_TOLERANCE {
+        return Err(format!("time of the first sample read back as {} instead of {}", read_info.epoch, info.epoch));
+    }
+
+    let mut read_timestamps = Vec::new();
+    let mut read_samples = Vec::new();
+    let (mut batch_timestamps, mut batch_samples) = (Vec::new(), Vec::new());
+    while recording.read_batch(&mut batch_timestamps, &mut batch_samples).map_err(|err| format!("reading {}: {}", path.display(), err))? {
+        read_timestamps.extend_from_slice(&batch_timestamps);
+        read_samples.extend_from_slice(&batch_samples);
+    }
+    if read_timestamps.len() != timestamps.len() || read_samples.len() != samples.len() {
+        return Err(format!("{} scans read back instead of {}", read_timestamps.len(), timestamps.len()));
+    }
+    if let Some(i) = (0..timestamps.len()).find(|&i| (read_timestamps[i] - timestamps[i]).abs() > TIMESTAMP_TOLERANCE) {
+        return Err(format!("timestamp of scan {} read back as {} instead of {}", i, read_timestamps[i], timestamps[i]));
+    }
+    if let Some(i) = (0..samples.len()).find(|&i| read_samples[i] != samples[i]) {
+        return Err(format!("sample {} of scan {} read back as {} instead of {}", i % names.len(), i/names.len(), read_samples[i], samples[i]));
+    }
+    Ok(())
+}
+
+/// Acquire `batches` batches and check them, then write them in every format to `directory`
+/// and read them back. Prints a line per check, true if every check passed
+pub fn run(mut task : DAQVTask, batches : u64, directory : &Path) -> bool {
+    let mut passed = true;
+    let mut report = |check : &str, result : Result<(), String>| {
+        match result {
+            Ok(()) => println!("PASS  {}", check),
+            Err(err) => {
+                println!("FAIL  {}: {}", check, err);
+                passed = false;
+            }
+        }
+    };
+
+    let mut timestamps = Vec::new();
+    let mut samples = Vec::new();
+    let acquired = acquire(&mut task, batches, &mut timestamps, &mut samples);
+    if let Err(err) = task.stop() {
+        eprintln!("{}", err);
+    }
+    for warning in task.take_warnings() {
+        eprintln!("{}", warning);
+    }
+    for err in task.take_errors() {
+        eprintln!("{}", err);
+    }
+    let failed = acquired.is_err();
+    report(&format!("acquire {} batches of {} scans", batches, task.sample_count()), acquired);
+    let info = match task.stream_info() {
+        Some(info) if !failed => info,
+        // Nothing to check without samples
+        _ => return false,
+    };
+
+    report("timestamps one sample period apart", check_timestamps(&info, &timestamps));
+    report("samples within the input ranges", check_ranges(&info, &samples));
+    for (format, extension) in [(OutputFormat::Csv, "csv"), (OutputFormat::Tdms, "tdms"), (OutputFormat::Arrow, "arrow"), (OutputFormat::Parquet, "parquet")] {
+        let path = directory.join(format!("selftest.{}", extension));
+        report(&format!("{} round trip", extension), round_trip(format, &path, &info, &timestamps, &samples));
+    }
+    passed
+}
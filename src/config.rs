@@ -0,0 +1,307 @@
+//! Acquisition configuration file
+//!
+//! A TOML or YAML file describing the whole task, e.g.
+//!
+//! ```toml
+//! backend = "nidaqmx"
+//!
+//! [[channels]]
+//! physical = "cDAQ9181-1FE3677Mod1/ai0"
+//! name = "supply"
+//! range = "-10:10"
+//! terminal = "diff"
+//!
+//! [timing]
+//! rate = 1000.0
+//! size = 1000
+//! mode = "continuous"
+//!
+//! [schedule]
+//! start_at = "2025-04-01T08:00:00+02:00"
+//! duration = 3600.0
+//!
+//! [trigger.start]
+//! type = "analog-edge"
+//! source = "cDAQ9181-1FE3677Mod1/ai0"
+//! level = 2.5
+//!
+//! [[output]]
+//! path = "rig1_%Y%m%d_%H%M%S.parquet"
+//! format = "parquet"
+//! rotate_interval = "hourly"
+//!
+//! [[output]]
+//! path = "event_%Y%m%d_%H%M%S.tdms"
+//! format = "tdms"
+//!
+//! [output.capture]
+//! pretrigger = 100
+//! posttrigger = 1000
+//! holdoff = 5.0
+//!
+//! [output.capture.trigger]
+//! type = "edge"
+//! channel = "supply"
+//! level = 9.5
+//! ```
+//!
+//! Every setting is optional except the channel list, missing ones take the command line defaults.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::Backend;
+use crate::backend::{SampleMode, Signal};
+use crate::channel::{ChannelConfig, expand_channels};
+use crate::queue::OverflowPolicy;
+use crate::schedule::Schedule;
+use crate::sink::{ArrowSink, Capture, CaptureSink, CsvFormat, CsvSink, Interval, OutputFormat, ParquetSink};
+use crate::sink::{RotatingSink, RotationPolicy, Sink, SinkFactory, TdmsSink, TimestampFormat};
+use crate::trigger::TriggerConfig;
+
+/// Configuration file syntax
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml
+}
+
+impl ConfigFormat {
+    /// Syntax of a file by its extension, TOML unless it is .yaml or .yml
+    pub fn of(path : &Path) -> ConfigFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("yaml") || extension.eq_ignore_ascii_case("yml") => ConfigFormat::Yaml,
+            _ => ConfigFormat::Toml,
+        }
+    }
+}
+
+/// Everything needed to run an acquisition
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Acquisition backend
+    pub backend : Backend,
+    /// Signals generated by the simulated backend, assigned to channels in turn
+    pub sim_signals : Vec<Signal>,
+    pub channels : Vec<ChannelConfig>,
+    pub timing : TimingConfig,
+    #[serde(skip_serializing_if = "Schedule::is_empty")]
+    pub schedule : Schedule,
+    #[serde(skip_serializing_if = "TriggerConfig::is_empty")]
+    pub trigger : TriggerConfig,
+    pub queue : QueueConfig,
+    /// Outputs every batch is written to
+    #[serde(rename = "output")]
+    pub outputs : Vec<OutputConfig>
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            backend : Backend::NIDAQmx,
+            sim_signals : vec![Signal::Sine],
+            channels : Vec::new(),
+            timing : TimingConfig::default(),
+            schedule : Schedule::default(),
+            trigger : TriggerConfig::default(),
+            queue : QueueConfig::default(),
+            outputs : vec![OutputConfig::default()]
+        }
+    }
+}
+
+impl Config {
+    /// Read a configuration file, its syntax is taken from the extension
+    pub fn load(path : &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+        let config = match ConfigFormat::of(path) {
+            ConfigFormat::Toml => toml::from_str(&text).map_err(|err| err.to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(&text).map_err(|err| err.to_string()),
+        };
+        config.map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Check the settings against each other: channels are given and none is used twice,
+    /// triggers fit the timing, and the schedule and queues can be run
+    pub fn validate(&self) -> Result<(), String> {
+        if self.channels.is_empty() {
+            return Err(String::from("No channels configured"));
+        }
+        expand_channels(&self.channels).map_err(|err| format!("Invalid channel list: {}", err))?;
+        if let Some(reference) = &self.trigger.reference {
+            if self.timing.mode != SampleMode::Finite {
+                return Err(String::from("Reference triggers require finite acquisition"));
+            }
+            if reference.pretrigger_samples >= self.timing.size {
+                return Err(format!("{} pretrigger samples don't leave any of the {} samples per batch after the reference trigger", reference.pretrigger_samples, self.timing.size));
+            }
+        }
+        self.schedule.check()?;
+        self.queue.check()?;
+        // Events are captured from a gap-free stream of scans
+        if self.outputs.iter().any(|output| output.capture.is_some()) && self.timing.mode != SampleMode::Continuous {
+            return Err(String::from("Software triggers require continuous acquisition"));
+        }
+        Ok(())
+    }
+
+    /// Write the configuration in the given syntax, loading the result gives the same configuration
+    pub fn to_string(&self, format : ConfigFormat) -> Result<String, String> {
+        match format {
+            ConfigFormat::Toml => toml::to_string(self).map_err(|err| err.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(self).map_err(|err| err.to_string()),
+        }
+    }
+}
+
+/// Sample clock settings
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimingConfig {
+    /// Sample rate [samples/sec]
+    pub rate : f64,
+    /// Number of samples per channel in each batch [N]
+    pub size : u64,
+    pub mode : SampleMode,
+    /// Take the time of the first sample from the device instead of the system clock
+    pub first_sample_timestamp : bool
+}
+
+impl Default for TimingConfig {
+    fn default() -> TimingConfig {
+        TimingConfig {
+            rate : 1000.0,
+            size : 1000,
+            mode : SampleMode::Finite,
+            first_sample_timestamp : false
+        }
+    }
+}
+
+/// Queues between the acquisition and the writer of every output
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    /// Batches waiting for each writer at most [N]
+    pub capacity : usize,
+    /// What to do when a writer falls behind and its queue is full
+    pub overflow : OverflowPolicy
+}
+
+impl QueueConfig {
+    /// Reject queues that can't hold a batch
+    pub fn check(&self) -> Result<(), String> {
+        if self.capacity == 0 {
+            return Err(String::from("The output queue must hold at least one batch"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for QueueConfig {
+    fn default() -> QueueConfig {
+        QueueConfig {
+            capacity : 16,
+            overflow : OverflowPolicy::Block
+        }
+    }
+}
+
+/// One output and how it is written
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Output file, "-" for stdout, or a chrono format pattern
+    pub path : String,
+    pub format : OutputFormat,
+    /// Name of the TDMS group the channels are written to
+    pub tdms_group : String,
+    /// Timestamp column format (CSV)
+    pub timestamp : TimestampFormat,
+    /// Column delimiter (CSV)
+    pub delimiter : char,
+    /// Digits after the decimal point of sample values (CSV)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub precision : Option<usize>,
+    /// Record the time of the first sample, sample rate, delimiter and input ranges in comment lines (CSV)
+    pub csv_metadata : bool,
+    /// Start a new file when the current one reaches this size [bytes]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotate_size : Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotate_interval : Option<Interval>,
+    /// Start a new file after this many samples per channel [N]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotate_samples : Option<u64>,
+    /// Number of most recent output files to keep [N]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep : Option<usize>,
+    /// Write only the scans around software trigger events, each event to a numbered file
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capture : Option<Capture>
+}
+
+impl Default for OutputConfig {
+    fn default() -> OutputConfig {
+        OutputConfig {
+            path : String::from("-"),
+            format : OutputFormat::Csv,
+            tdms_group : String::from("Data"),
+            timestamp : TimestampFormat::ISO8601,
+            delimiter : ',',
+            precision : None,
+            csv_metadata : false,
+            rotate_size : None,
+            rotate_interval : None,
+            rotate_samples : None,
+            keep : None,
+            capture : None
+        }
+    }
+}
+
+impl OutputConfig {
+    /// Open the sink writing this output
+    pub fn open(&self) -> io::Result<Box<dyn Sink>> {
+        let format = CsvFormat {
+            timestamp : self.timestamp,
+            delimiter : self.delimiter,
+            precision : self.precision,
+            metadata : self.csv_metadata
+        };
+        let policy = RotationPolicy {
+            max_bytes : self.rotate_size,
+            interval : self.rotate_interval,
+            max_samples : self.rotate_samples
+        };
+        if self.path == "-" {
+            if self.format != OutputFormat::Csv {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{:?} output requires an output file", self.format)));
+            }
+            if policy.max_bytes.is_some() || policy.interval.is_some() || policy.max_samples.is_some() || self.keep.is_some() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "rotating output requires an output file pattern"));
+            }
+            if self.capture.is_some() {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "event capture requires an output file pattern"));
+            }
+            return Ok(Box::new(CsvSink::create(Path::new("-"), format)?));
+        }
+        let tdms_group = self.tdms_group.clone();
+        let open : SinkFactory = match self.format {
+            OutputFormat::Csv => Box::new(move |path| Ok(Box::new(CsvSink::create(path, format.clone())?))),
+            OutputFormat::Tdms => Box::new(move |path| Ok(Box::new(TdmsSink::create(path, &tdms_group)?))),
+            OutputFormat::Arrow => Box::new(|path| Ok(Box::new(ArrowSink::create(path)?))),
+            OutputFormat::Parquet => Box::new(|path| Ok(Box::new(ParquetSink::create(path)?))),
+        };
+        match &self.capture {
+            Some(capture) => Ok(Box::new(CaptureSink::new(&self.path, capture.clone(), open)?)),
+            None => Ok(Box::new(RotatingSink::new(&self.path, policy, self.keep, open)?)),
+        }
+    }
+}
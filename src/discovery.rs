@@ -0,0 +1,87 @@
+//! Devices and analog input channels available to a backend
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::MeasurementMode;
+use crate::backend::AcquisitionBackend;
+use crate::channel::Range;
+use crate::error::DAQmxError;
+
+/// Device with what its analog inputs support
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceReport {
+    pub name : String,
+    pub product_type : String,
+    pub serial_number : u32,
+    /// Modules in the slots of a chassis
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub modules : Vec<String>,
+    /// Analog input voltage ranges [V]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub voltage_ranges : Vec<Range>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub channels : Vec<ChannelReport>
+}
+
+/// Analog input physical channel
+#[derive(Clone, Debug, Serialize)]
+pub struct ChannelReport {
+    pub name : String,
+    pub terminal_configs : Vec<MeasurementMode>
+}
+
+/// Query every device in the system
+pub fn discover(backend : &dyn AcquisitionBackend) -> Result<Vec<DeviceReport>, DAQmxError> {
+    let mut devices = Vec::new();
+    for name in backend.device_names()? {
+        let info = backend.device_info(&name)?;
+        // Chassis have no analog inputs of their own
+        let voltage_ranges = if info.ai_channels.is_empty() { Vec::new() } else { backend.voltage_ranges(&name)? };
+        let mut channels = Vec::new();
+        for channel in info.ai_channels {
+            channels.push(ChannelReport {
+                terminal_configs : backend.terminal_configs(&channel)?,
+                name : channel
+            });
+        }
+        devices.push(DeviceReport {
+            name : info.name,
+            product_type : info.product_type,
+            serial_number : info.serial_number,
+            modules : info.modules,
+            voltage_ranges : voltage_ranges,
+            channels : channels
+        });
+    }
+    Ok(devices)
+}
+
+/// Write the devices as an indented list, one physical channel per line
+pub fn print_devices(out : &mut dyn Write, devices : &[DeviceReport]) -> io::Result<()> {
+    if devices.is_empty() {
+        return writeln!(out, "No devices found");
+    }
+    for device in devices {
+        // Serial numbers are shown in hex, as in NI MAX and cDAQ device names
+        write!(out, "{} ({}", device.name, device.product_type)?;
+        if device.serial_number != 0 {
+            write!(out, ", serial {:X}", device.serial_number)?;
+        }
+        writeln!(out, ")")?;
+        if !device.modules.is_empty() {
+            writeln!(out, "  modules: {}", device.modules.join(", "))?;
+        }
+        if !device.voltage_ranges.is_empty() {
+            let ranges : Vec<String> = device.voltage_ranges.iter().map(|range| format!("{}:{}", range.min, range.max)).collect();
+            writeln!(out, "  voltage ranges: {} V", ranges.join(", "))?;
+        }
+        let width = device.channels.iter().map(|channel| channel.name.len()).max().unwrap_or(0);
+        for channel in &device.channels {
+            let terminals : Vec<String> = channel.terminal_configs.iter().map(|terminal| format!("{:?}", terminal)).collect();
+            writeln!(out, "  {:width$}  {}", channel.name, terminals.join(", "), width = width)?;
+        }
+    }
+    Ok(())
+}
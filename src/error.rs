@@ -0,0 +1,138 @@
+use std::fmt;
+
+use crate::{Backend, MeasurementMode};
+use crate::backend::{ERR_DEVICE_MEMORY_OVERFLOW, ERR_SAMPLES_NO_LONGER_AVAILABLE};
+use crate::channel::Range;
+
+/// Nonzero status returned by an NI-DAQmx call
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DAQmxError {
+    /// The call failed (negative status code)
+    Error {
+        /// Name of the DAQmx function that was called
+        function : &'static str,
+        code : i32,
+        /// Driver's description of the error
+        message : String
+    },
+    /// The call succeeded but the driver reported a problem (positive status code)
+    Warning {
+        /// Name of the DAQmx function that was called
+        function : &'static str,
+        code : i32,
+        /// Driver's description of the warning
+        message : String
+    }
+}
+
+impl DAQmxError {
+    /// Classify a nonzero status code by its sign
+    pub fn new(function : &'static str, code : i32, message : String) -> DAQmxError {
+        if code < 0 {
+            DAQmxError::Error { function : function, code : code, message : message }
+        } else {
+            DAQmxError::Warning { function : function, code : code, message : message }
+        }
+    }
+
+    /// Name of the DAQmx function that returned the status
+    pub fn function(&self) -> &'static str {
+        match self {
+            DAQmxError::Error { function, .. } | DAQmxError::Warning { function, .. } => function
+        }
+    }
+
+    /// DAQmx status code, negative for errors and positive for warnings
+    pub fn code(&self) -> i32 {
+        match self {
+            DAQmxError::Error { code, .. } | DAQmxError::Warning { code, .. } => *code
+        }
+    }
+
+    /// Driver's description of the status
+    pub fn message(&self) -> &str {
+        match self {
+            DAQmxError::Error { message, .. } | DAQmxError::Warning { message, .. } => message
+        }
+    }
+
+    pub fn is_warning(&self) -> bool {
+        matches!(self, DAQmxError::Warning { .. })
+    }
+
+    /// Whether a buffer overran and samples were lost, the task restarts on the next read
+    pub fn is_overrun(&self) -> bool {
+        self.code() == ERR_SAMPLES_NO_LONGER_AVAILABLE || self.code() == ERR_DEVICE_MEMORY_OVERFLOW
+    }
+}
+
+impl fmt::Display for DAQmxError {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = if self.is_warning() { "warning" } else { "error" };
+        write!(f, "{} {} {}", self.function(), kind, self.code())?;
+        if !self.message().is_empty() {
+            write!(f, ": {}", self.message().trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DAQmxError {}
+
+/// Error setting up an acquisition
+#[derive(Clone, PartialEq, Debug)]
+pub enum Error {
+    /// A driver call failed
+    DAQmx(DAQmxError),
+    /// The device has no input range covering the requested one
+    UnsupportedRange {
+        channel : String,
+        range : Range,
+        /// Ranges the device supports
+        supported : Vec<Range>
+    },
+    /// The module doesn't support the requested terminal configuration
+    UnsupportedTerminal {
+        channel : String,
+        terminal : MeasurementMode,
+        /// Terminal configurations the channel supports
+        supported : Vec<MeasurementMode>
+    },
+    /// The backend was left out of this build
+    BackendUnavailable(Backend),
+    /// The task has no channels
+    NoChannels,
+    /// The configuration was rejected by [`Config::validate`](crate::Config::validate)
+    InvalidConfig(String)
+}
+
+impl From<DAQmxError> for Error {
+    fn from(err : DAQmxError) -> Error {
+        Error::DAQmx(err)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f : &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::DAQmx(err) => err.fmt(f),
+            Error::UnsupportedRange { channel, range, supported } => {
+                write!(f, "{}: input range {}:{} V is not supported, the device supports", channel, range.min, range.max)?;
+                for (i, range) in supported.iter().enumerate() {
+                    write!(f, "{} {}:{}", if i > 0 { "," } else { "" }, range.min, range.max)?;
+                }
+                Ok(())
+            }
+            Error::UnsupportedTerminal { channel, terminal, supported } => {
+                write!(f, "{}: terminal configuration {:?} is not supported, the channel supports {:?}", channel, terminal, supported)
+            }
+            Error::BackendUnavailable(backend) => {
+                write!(f, "The {:?} backend is not available, daqlogger was built without the nidaqmx feature", backend)
+            }
+            Error::NoChannels => write!(f, "No channels configured"),
+            Error::InvalidConfig(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
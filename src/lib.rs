@@ -0,0 +1,127 @@
+//! Analog input acquisition with NI-DAQmx devices, or a simulated device, logged to CSV,
+//! TDMS, Arrow and Parquet files
+//!
+//! A [`DAQVTask`] acquires batches of scans from a list of [`ChannelConfig`] entries on an
+//! [`AcquisitionBackend`], every read returning a [`Batch`] that borrows its buffers. Batches
+//! can be written to [`Sink`]s directly, or by [`pipeline::run`], which reads the device on a
+//! thread of its own and writes every output from another. A whole acquisition is described
+//! by a [`Config`], which can be loaded from a TOML or YAML file.
+//!
+//! ```no_run
+//! use daqlogger::{Backend, Config, DAQVTask};
+//! use daqlogger::channel::parse_channel_list;
+//!
+//! let mut config = Config::default();
+//! config.backend = Backend::Simulated;
+//! config.channels = parse_channel_list("Dev1/ai0:1").unwrap();
+//! let mut task = DAQVTask::from_config(&config).unwrap();
+//! let batch = task.acquire_samples().unwrap();
+//! for (channel, samples) in batch.columns() {
+//!     println!("{}: {} samples from {:?}", channel.name, samples.len(), batch.first_timestamp());
+//! }
+//! ```
+//!
+//! [`DAQVTask::batches`] reads one batch after the other as an iterator, until a schedule ends
+//! or a flag is set, so the standard adapters apply. With the `stream` feature,
+//! `stream::BatchStream` yields the same batches as an async `Stream`.
+//!
+//! ```no_run
+//! # use daqlogger::{Backend, Config, DAQVTask};
+//! # use daqlogger::channel::parse_channel_list;
+//! use daqlogger::schedule::Schedule;
+//!
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! # let mut config = Config::default();
+//! # config.backend = Backend::Simulated;
+//! # config.channels = parse_channel_list("Dev1/ai0:1")?;
+//! # let mut task = DAQVTask::from_config(&config)?;
+//! let schedule = Schedule { total_samples : Some(10_000), ..Schedule::default() };
+//! // Every tenth batch, a failed read ends the loop
+//! for batch in task.batches().scheduled(&schedule).step_by(10) {
+//!     let batch = batch?;
+//!     for (time, scan) in batch.as_batch().rows() {
+//!         println!("{} {:?}", time, scan);
+//!     }
+//! }
+//! task.stop()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Callers never need `unsafe`: the driver calls are wrapped by the NI-DAQmx backend, which
+//! reports failures as [`DAQmxError`]s. That backend is built with the `nidaqmx` feature, on by
+//! default, without it only the simulated backend is available and the NI-DAQmx driver isn't
+//! needed to build.
+
+// Struct literals name every field, `field : field` included
+#![allow(clippy::redundant_field_names)]
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Terminal configuration of an analog input
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MeasurementMode {
+    /// Referenced single-ended mode
+    #[default]
+    RSE,
+    /// Non-referenced single-ended mode
+    NRSE,
+    /// Differential mode
+    DIFF,
+    /// Pseudodifferential mode
+    PSEUDODIFF
+}
+
+/// Where samples are acquired from
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// NI-DAQmx driver and hardware
+    #[value(name = "nidaqmx")]
+    NIDAQmx,
+    /// Simulated device, no driver or hardware required
+    Simulated
+}
+
+impl Backend {
+    /// Create the backend, the simulated one generates `sim_signals` on its channels in turn
+    pub fn open(self, sim_signals : &[Signal]) -> Result<Box<dyn AcquisitionBackend>, Error> {
+        Ok(match self {
+            #[cfg(feature = "nidaqmx")]
+            Backend::NIDAQmx => Box::new(NIDAQmxBackend::new()?),
+            #[cfg(not(feature = "nidaqmx"))]
+            Backend::NIDAQmx => return Err(Error::BackendUnavailable(self)),
+            Backend::Simulated => Box::new(SimulatedBackend::new(sim_signals)),
+        })
+    }
+}
+
+pub mod error;
+pub mod channel;
+pub mod backend;
+pub mod batch;
+pub mod sink;
+pub mod config;
+pub mod scale;
+pub mod trigger;
+pub mod queue;
+pub mod schedule;
+pub mod discovery;
+pub mod pipeline;
+#[cfg(feature = "stream")]
+pub mod stream;
+mod task;
+
+pub use backend::{AcquisitionBackend, SampleMode, Signal, SimulatedBackend};
+#[cfg(feature = "nidaqmx")]
+pub use backend::NIDAQmxBackend;
+pub use batch::{Batch, Batches, OwnedBatch};
+pub use channel::ChannelConfig;
+pub use config::Config;
+pub use error::{DAQmxError, Error};
+pub use sink::{Sink, StreamInfo};
+pub use task::DAQVTask;
@@ -1,290 +1,315 @@
-extern crate clap;
-extern crate chrono;
+// Struct literals name every field, `field : field` included
+#![allow(clippy::redundant_field_names)]
 
-use chrono::TimeDelta;
-use core::ffi::c_char;
-use std::ffi::CString;
-use ni_daqmx_sys;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use chrono::Local;
 use clap::Parser;
-use clap::{Arg, ArgMatches, ValueEnum};
+use signal_hook::consts::{SIGINT, SIGTERM};
 
-use std::time::{SystemTime};
+use daqlogger::{Backend, DAQVTask, MeasurementMode};
+use daqlogger::sink::open_recording;
+use daqlogger::{discovery, pipeline};
 
-use chrono::prelude::*;
+mod cli;
 
+use cli::{monitor, selftest};
+use cli::args::{AcquireArgs, Args, Command, ListArgs, MonitorArgs, OutputArgs, SelftestArgs, configure_acquisition, configure_output, configure_task};
 
-static SAMPLES_PER_SECOND : ni_daqmx_sys::float64 = 1000.0;
-static SAMPLES: i32 = 1000;
-static CHANNELS: i32 = 2;
-
-
-#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
-#[derive(Debug)]
-enum MeasurementMode {
-    /// Referenced single-ended mode
-    RSE,
-    /// Non-referenced single-ended mode
-    NRSE,
-    /// Differential mode
-    DIFF,
-    /// Pseudodifferential mode
-    PSEUDODIFF
-}
-
-/// VeSys XML project post-processor 
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// The names of the physical channels to use to create virtual channels. You can specify a list or range of physical channels.
-    ///
-    /// SYNTAX: <device>/<channel>, <device>/<channel>, ...
-    ///
-    /// EXAMPLE: cDAQ9181-1FE3677Mod1/ai0, cDAQ9181-1FE3677Mod1/ai8
-    channels: String,
-    #[arg(value_enum, default_value_t = MeasurementMode::RSE)]
-    /// Terminal configuration mode
-    mode: MeasurementMode,
-    /// Sample rate [samples/sec]
-    #[arg(short, long, default_value_t = 1000.0)]
-    rate: f64,
-    /// Number of samples to take for each measurement batch [N]
-    #[arg(short, long, default_value_t = 1000)]
-    size: u64,
-}
-
-macro_rules! check_err {
-    ($prefix:expr,$err:expr) => {
-        if $err != 0 {
-            eprintln!("{} error: {:?}", $prefix, $err);
+/// Print the devices of a backend
+fn list_devices(args : &ListArgs) -> ExitCode {
+    let backend = match args.backend.open(&[]) {
+        Ok(backend) => backend,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
         }
     };
-}
-
-macro_rules! return_if_err {
-    ($prefix:expr,$err:expr) => {
-        if $err != 0 {
-            eprintln!("{} error: {:?}", $prefix, $err);
-            return Err($err);
+    let devices = match discovery::discover(backend.as_ref()) {
+        Ok(devices) => devices,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
         }
     };
+    let mut out = io::stdout().lock();
+    let result = if args.json {
+        serde_json::to_writer_pretty(&mut out, &devices).map_err(io::Error::from).and_then(|()| writeln!(out))
+    } else {
+        discovery::print_devices(&mut out, &devices)
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-
-#[derive(Debug)]
-struct DAQVTask {
-    task_handle : ni_daqmx_sys::TaskHandle,
-    samples : Vec<ni_daqmx_sys::float64>,
-    timestamps : Vec<DateTime<Local>>,
-    channels : usize,
-    sample_rate : ni_daqmx_sys::float64
+/// Exit status for invalid options or configuration files, as clap uses for usage errors
+const EXIT_USAGE : u8 = 2;
+
+/// Stop on SIGINT and SIGTERM after flushing the outputs, a second signal exits immediately.
+/// The number of the signal that arrived is stored in `signal`
+fn register_signals(signal : &Arc<AtomicUsize>) -> io::Result<()> {
+    let signalled = Arc::new(AtomicBool::new(false));
+    for number in [SIGINT, SIGTERM] {
+        // Checked before the flag is set, so only the second signal exits
+        signal_hook::flag::register_conditional_shutdown(number, 128 + number, signalled.clone())?;
+        signal_hook::flag::register(number, signalled.clone())?;
+        signal_hook::flag::register_usize(number, signal.clone(), number as usize)?;
+    }
+    Ok(())
 }
 
-impl DAQVTask {
-    fn new(channels : &str, mode : MeasurementMode, sample_rate : ni_daqmx_sys::float64, sample_count : u64) -> Result<DAQVTask, i32> {
-        let mut task_handle : ni_daqmx_sys::TaskHandle = std::ptr::null_mut();
-        unsafe {
-            // Create measurement task
-            return_if_err!("DAQmxCreateTask", ni_daqmx_sys::DAQmxCreateTask(std::ptr::null(), &mut task_handle));
-
-            // Translate mode options
-            let mode = match mode {
-                MeasurementMode::RSE => ni_daqmx_sys::DAQmx_Val_RSE,
-                MeasurementMode::NRSE => ni_daqmx_sys::DAQmx_Val_NRSE,
-                MeasurementMode::DIFF => ni_daqmx_sys::DAQmx_Val_Diff,
-                MeasurementMode::PSEUDODIFF => ni_daqmx_sys::DAQmx_Val_PseudoDiff,
-            };
-
-            let ch_name = CString::new(channels).expect("CString::new failed");
-            let ch_name_ptr: *const c_char = ch_name.as_ptr();
-        
-            // Create channels and set measurement mode
-            return_if_err!("DAQmxCreateAIVoltageChan", ni_daqmx_sys::DAQmxCreateAIVoltageChan(task_handle, ch_name_ptr, std::ptr::null(), mode, -10.0, 10.0, ni_daqmx_sys::DAQmx_Val_Volts, std::ptr::null()));
+/// Stop on SIGINT and SIGTERM, or exit immediately on the second one, reporting failure
+fn handle_signals() -> Result<Arc<AtomicUsize>, ExitCode> {
+    let signal = Arc::new(AtomicUsize::new(0));
+    match register_signals(&signal) {
+        Ok(()) => Ok(signal),
+        Err(err) => {
+            eprintln!("Cannot handle signals: {}", err);
+            Err(ExitCode::FAILURE)
         }
-            // Find number of channels created
-            let mut channels : u32 = 0;
-        unsafe {
-            return_if_err!("DAQmxGetTaskNumChans", ni_daqmx_sys::DAQmxGetTaskNumChans(task_handle, &mut channels));
-        }
-            assert!(channels > 0);
-
-        unsafe {
-            // Set sample rate, sample count, trigger mode
-            return_if_err!("DAQmxCfgSampClkTiming", ni_daqmx_sys::DAQmxCfgSampClkTiming(task_handle, std::ptr::null(), sample_rate, ni_daqmx_sys::DAQmx_Val_Rising, ni_daqmx_sys::DAQmx_Val_FiniteSamps, sample_count));
-        }
-
-        let mut samples = Vec::<ni_daqmx_sys::float64>::new();
-        let buffer_size = (channels as usize)*(sample_count as usize);
-        samples.resize(buffer_size, 0.0);
-
-        let mut timestamps = Vec::<DateTime<Local>>::new();
-        timestamps.resize(buffer_size, Local::now());
+    }
+}
 
-        Ok(DAQVTask {
-            task_handle : task_handle,
-            samples : samples, // data buffer
-            timestamps : timestamps,
-            sample_rate : sample_rate,
-            channels : channels.try_into().unwrap()
-        })
+/// Exit status of a run that `failed` or was stopped by `signal`
+fn exit_code(failed : bool, signal : &AtomicUsize) -> ExitCode {
+    match signal.load(Ordering::Relaxed) {
+        _ if failed => ExitCode::FAILURE,
+        0 => ExitCode::SUCCESS,
+        number => ExitCode::from(128 + number as u8),
     }
+}
 
-    /// Read samples, returns number of sampes read
-    fn acquire_samples(&mut self) -> Result<i32, i32> {
-        let mut read : i32 = -1;
+/// Acquire and write the outputs, each by a thread of its own
+fn run_acquire(args : &AcquireArgs) -> ExitCode {
+    let config = match configure_acquisition(args) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
 
-        let start_time = Local::now();
+    if let Some(format) = args.dump_config {
+        return match config.to_string(format) {
+            Ok(text) => {
+                print!("{}", text);
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                ExitCode::FAILURE
+            }
+        };
+    }
 
-        unsafe {
-            // Start
-            return_if_err!("DAQmxStartTask", ni_daqmx_sys::DAQmxStartTask(self.task_handle));
-            // Read
-            return_if_err!("DAQmxReadAnalogF64", 
-                ni_daqmx_sys::DAQmxReadAnalogF64(
-                    self.task_handle, 
-                    ni_daqmx_sys::DAQmx_Val_Auto, 
-                    10.0, 
-                    ni_daqmx_sys::DAQmx_Val_GroupByScanNumber as u32, 
-                    self.samples.as_mut_ptr(), 
-                    self.samples.len() as u32, 
-                    &mut read, std::ptr::null_mut()));
+    let signal = match handle_signals() {
+        Ok(signal) => signal,
+        Err(code) => return code,
+    };
 
-            // Stop
-            return_if_err!("DAQmxStopTask", ni_daqmx_sys::DAQmxStopTask(self.task_handle))
+    let task = match DAQVTask::from_config(&config) {
+        Ok(task) => task,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
         }
+    };
 
-        // Fill timestamps
-        let period = TimeDelta::nanoseconds((1e9*(1.0/self.sample_rate)) as i64);
-        let p = start_time + period*2;
-        for i in 0..read {
-            let timestamp = start_time + period*i;
-            let i : usize = i.try_into().unwrap();
-            self.timestamps[i] = timestamp;
+    let mut outputs = Vec::new();
+    for output in &config.outputs {
+        match output.open() {
+            Ok(sink) => outputs.push((output.path.clone(), sink)),
+            Err(err) => {
+                eprintln!("{}: {}", output.path, err);
+                return ExitCode::FAILURE;
+            }
         }
-
-        self.samples_read = read;
-
-        return read;
     }
 
-    /// Get read samples from the buffer
-    fn get_samples(self) -> Result<&[ni_daqmx_sys::float64], i32> {
-        // return slice to buffer in case not all samples were read
-        return Ok(&self.samples[0..read.try_into().unwrap()]);
+    if let Some(start_at) = config.schedule.start_at.filter(|start_at| *start_at > Local::now()) {
+        eprintln!("Waiting until {} to start", start_at);
     }
-
-    fn get_timestamps(self) -> Result<&[ni_daqmx_sys::float64], i32> {
-        // return slice to buffer in case not all samples were read
-        return Ok(&self.timestamps[0..read.try_into().unwrap()]);
+    let report = pipeline::run(task, outputs, &config, signal.clone());
+    for warning in &report.warnings {
+        eprintln!("{}", warning);
+    }
+    for err in &report.errors {
+        eprintln!("{}", err);
+    }
+    if report.overruns > 0 {
+        eprintln!("{} buffer overruns, {} samples per channel dropped", report.overruns, report.samples_dropped);
+    }
+    for (stats, output) in report.stats.iter().zip(&config.outputs) {
+        eprintln!("{}: {} batches queued, queue depth up to {} of {}, {} dropped, waited {} times",
+            output.path, stats.pushed, stats.max_depth, config.queue.capacity, stats.dropped, stats.blocked);
     }
-}
 
+    exit_code(!report.errors.is_empty(), &signal)
+}
 
-impl Drop for DAQVTask {
-    /// Clean up
-    fn drop(&mut self) {
+/// Replay or convert a recording, converting is replaying at no particular speed
+fn run_replay(input : &Path, delimiter : char, args : &OutputArgs, speed : f64) -> ExitCode {
+    if !(speed >= 0.0 && speed.is_finite()) {
+        eprintln!("Invalid speed {}, expected 0 or more", speed);
+        return ExitCode::from(EXIT_USAGE);
+    }
+    let mut outputs = Vec::new();
+    if let Err(err) = configure_output(&mut outputs, args) {
+        eprintln!("{}", err);
+        return ExitCode::from(EXIT_USAGE);
+    }
+    let output = &outputs[0];
 
-        if self.task_handle != std::ptr::null_mut() {
-            unsafe {
-                let err = ni_daqmx_sys::DAQmxStopTask(self.task_handle);
-                check_err!("DAQmxStopTask", err);
-                let err = ni_daqmx_sys::DAQmxClearTask(self.task_handle);
-                check_err!("DAQmxClearTask", err);
-            }
+    let signal = match handle_signals() {
+        Ok(signal) => signal,
+        Err(code) => return code,
+    };
+    let mut recording = match open_recording(input, delimiter) {
+        Ok(recording) => recording,
+        Err(err) => {
+            eprintln!("{}: {}", input.display(), err);
+            return ExitCode::FAILURE;
         }
-    }
+    };
+    let mut sink = match output.open() {
+        Ok(sink) => sink,
+        Err(err) => {
+            eprintln!("{}: {}", output.path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let failed = match pipeline::replay(recording.as_mut(), sink.as_mut(), speed, &signal) {
+        Ok(()) => false,
+        Err(err) => {
+            eprintln!("{} to {}: {}", input.display(), output.path, err);
+            true
+        }
+    };
+    exit_code(failed, &signal)
 }
 
-fn main() {
-
-    let s = 0.5;
-    let msf:f64 = (1000.0*s);
-    let msu:u32 = msf.floor() as u32;
-    println!("{}", msu);
-    let args = Args::parse();
-    return;
-
-    let mut daqmx = DAQVTask::new(&args.channels, MeasurementMode::RSE, args.rate, args.size);
-    loop {
-    match daqmx {
-        Ok(ref mut task) => {
-            let channels = task.channels;
-            task.channels = 1;
-            // mark start time
-            match task.read_samples() {
-                Ok(samples) => {
-                    for row in 0..samples.len()/channels {
-                        let row_offset = row*channels;
-                        let time = Local::now();
-                        print!("{:?}", time.format("%Y-%m-%d %H:%M:%S.%3f").to_string());
-                        //print!("{:?}", time.format("%s").to_string());
-                        for column in 0..channels {
-                            //if column > 0 { print!(",") };
-                            print!(", {}", samples[row_offset + column]);
-                        }
-                        print!("\n");
-                    }
-                }
-                Err(code) => {
-                    eprintln!("One of NI-DAQmx API calls returned an error code: {}", code);        
-                }
+/// Run the checks of a short acquisition and report whether all of them passed
+fn run_selftest(args : &SelftestArgs) -> ExitCode {
+    let mut task = args.task.clone();
+    if task.config.is_none() {
+        task.backend = task.backend.or(Some(Backend::Simulated));
+    }
+    if task.channels.is_none() && task.config.is_none() {
+        // Test the first two analog inputs of the first device that has some
+        let devices = match task.backend.unwrap().open(&[]).and_then(|backend| Ok(discovery::discover(backend.as_ref())?)) {
+            Ok(devices) => devices,
+            Err(err) => {
+                eprintln!("{}", err);
+                return ExitCode::FAILURE;
             }
-        } 
-        Err(code) => {
-            eprintln!("One of NI-DAQmx API calls returned an error code: {}", code);
-            return;
+        };
+        let channels = match devices.iter().map(|device| &device.channels).find(|channels| !channels.is_empty()) {
+            Some(channels) => &channels[..std::cmp::min(channels.len(), 2)],
+            None => {
+                eprintln!("No device with analog inputs found");
+                return ExitCode::FAILURE;
+            }
+        };
+        let names : Vec<&str> = channels.iter().map(|channel| channel.name.as_str()).collect();
+        task.channels = Some(names.join(", "));
+        if task.mode.is_none() && !channels[0].terminal_configs.contains(&MeasurementMode::default()) {
+            task.mode = channels[0].terminal_configs.first().copied();
         }
     }
-
+    if args.batches == 0 {
+        eprintln!("At least one batch must be acquired");
+        return ExitCode::from(EXIT_USAGE);
     }
+    let config = match configure_task(&task) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    };
 
+    let channels : Vec<&str> = config.channels.iter().map(|channel| channel.physical.as_str()).collect();
+    println!("Testing {} on the {:?} backend at {} samples/sec", channels.join(", "), config.backend, config.timing.rate);
+    let task = match DAQVTask::from_config(&config) {
+        Ok(task) => task,
+        Err(err) => {
+            println!("FAIL  create task: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    return;
-    unsafe {
-        let mut task_handle : ni_daqmx_sys::TaskHandle = std::ptr::null_mut();
-        //let ch : c_str
-        //let task_name: *const c_char = CString::new("daq01").expect("CString::new failed").as_ptr();
-
-        let err = ni_daqmx_sys::DAQmxCreateTask(std::ptr::null(), &mut task_handle);
-        check_err!("DAQmxCreateTask", err);
-        
-        
-
-        let ch_name = CString::new("cDAQ9181-1FE3677Mod1/ai0, cDAQ9181-1FE3677Mod1/ai8").expect("CString::new failed");
-        let ch_name_ptr: *const c_char = ch_name.as_ptr();
-        let err = ni_daqmx_sys::DAQmxCreateAIVoltageChan(task_handle, ch_name_ptr, std::ptr::null(), ni_daqmx_sys::DAQmx_Val_RSE, -10.0, 10.0, ni_daqmx_sys::DAQmx_Val_Volts, std::ptr::null());
-        check_err!("DAQmxCreateAIVoltageChan", err);
-
-        let mut channels : u32 = 0;
-        let err = ni_daqmx_sys::DAQmxGetTaskNumChans(task_handle, &mut channels);
-        check_err!("DAQmxGetTaskNumChans", err);
-        println!("Channels {}", channels);
-
-        let err = ni_daqmx_sys::DAQmxCfgSampClkTiming(task_handle, std::ptr::null(), SAMPLES_PER_SECOND, ni_daqmx_sys::DAQmx_Val_Rising, ni_daqmx_sys::DAQmx_Val_FiniteSamps, 1000);
-        check_err!("DAQmxCfgSampClkTiming", err);
-        let err = ni_daqmx_sys::DAQmxStartTask(task_handle);
-        check_err!("DAQmxStartTask", err);
-        let mut data : [ni_daqmx_sys::float64; (CHANNELS*SAMPLES) as usize] = [0.0; (CHANNELS*SAMPLES) as usize];
-        let data_ptr: *mut f64 = data.as_mut_ptr();
-        let mut read : i32 = -1;
-        let err = ni_daqmx_sys::DAQmxReadAnalogF64(task_handle, SAMPLES, 10.0, ni_daqmx_sys::DAQmx_Val_GroupByScanNumber as u32, data_ptr, (CHANNELS*SAMPLES) as u32, &mut read, std::ptr::null_mut());
-        
-
-        check_err!("DAQmxReadAnalogF64", err);
-        //println!("DAQmxReadAnalogF64 {:?}", data);
-        let err = ni_daqmx_sys::DAQmxStopTask(task_handle);
-        check_err!("DAQmxStopTask", err);
-        println!("{}", read);
-
-        // for i in 0..data.len() {
-        //     println!("{}", data[i]);
-        // }
+    let directory = match &args.keep_files {
+        Some(directory) => directory.clone(),
+        None => std::env::temp_dir().join(format!("daqlogger-selftest-{}", std::process::id())),
+    };
+    if let Err(err) = std::fs::create_dir_all(&directory) {
+        eprintln!("{}: {}", directory.display(), err);
+        return ExitCode::FAILURE;
+    }
+    let passed = selftest::run(task, args.batches, &directory);
+    if args.keep_files.is_none() {
+        if let Err(err) = std::fs::remove_dir_all(&directory) {
+            eprintln!("{}: {}", directory.display(), err);
+        }
+    }
+    if passed { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
 
-        for i in 0..data.len()/2 {
-            let j = i*2;
-            println!("{} {}", data[j], data[j+1]);
+/// Acquire continuously and show the channels until stopped
+fn run_monitor(args : &MonitorArgs) -> ExitCode {
+    if args.interval.is_nan() || args.interval <= 0.0 {
+        eprintln!("The refresh interval must be greater than zero, got {} s", args.interval);
+        return ExitCode::from(EXIT_USAGE);
+    }
+    let mut task = args.task.clone();
+    task.continuous = true;
+    let config = match configure_task(&task) {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::from(EXIT_USAGE);
         }
+    };
+    let signal = match handle_signals() {
+        Ok(signal) => signal,
+        Err(code) => return code,
+    };
+    let task = match DAQVTask::from_config(&config) {
+        Ok(task) => task,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Monitoring ends with a signal, that isn't a failure
+    match monitor::run(task, args.interval, &signal) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
 
+/// Exit status: 0 on success, 1 on errors, 2 for invalid options and 128 + signal number when
+/// an acquisition, replay or conversion was stopped by a signal, as a shell reports it
+fn main() -> ExitCode {
+    let args = Args::parse();
+    match &args.command {
+        Command::Acquire(args) => run_acquire(args),
+        Command::List(args) => list_devices(args),
+        Command::Replay(args) => run_replay(&args.input, args.input_delimiter, &args.output, args.speed),
+        Command::Convert(args) => run_replay(&args.input, args.input_delimiter, &args.output, 0.0),
+        Command::Selftest(args) => run_selftest(args),
+        Command::Monitor(args) => run_monitor(args),
     }
-    
 }
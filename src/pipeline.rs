@@ -0,0 +1,197 @@
+//! Acquisition running on a thread of its own, every output written by another
+
+use std::borrow::Borrow;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use chrono::prelude::*;
+use chrono::TimeDelta;
+
+use crate::DAQVTask;
+use crate::batch::OwnedBatch;
+use crate::config::Config;
+use crate::queue::{BoundedQueue, PushError, QueueStats};
+use crate::schedule::Schedule;
+use crate::sink::{Recording, Sink};
+
+/// Batches on their way to the writer of one output
+type OutputQueue = Arc<BoundedQueue<Arc<OwnedBatch>>>;
+
+/// Queue the batch for every output. False once every writer is done
+fn queue_batch(batch : OwnedBatch, queues : &[OutputQueue]) -> Result<bool, String> {
+    let batch = Arc::new(batch);
+    for queue in queues {
+        match queue.push(batch.clone()) {
+            Ok(()) | Err(PushError::Closed) => {}
+            Err(PushError::Full) => return Err(String::from("Output queue full, stopping acquisition")),
+        }
+    }
+    Ok(!queues.iter().all(|queue| queue.is_closed()))
+}
+
+/// Sleep until `time` or until `stop` is set
+fn wait_until(time : DateTime<Local>, stop : &AtomicUsize) {
+    while stop.load(Ordering::Relaxed) == 0 {
+        let remaining = time - Local::now();
+        if remaining <= TimeDelta::zero() {
+            return;
+        }
+        // Wake up regularly to notice a stop
+        thread::sleep(std::cmp::min(remaining, TimeDelta::milliseconds(100)).to_std().unwrap_or_default());
+    }
+}
+
+/// Read batches and queue them for every output until a stop condition of the schedule is
+/// met, all writers are done, `stop` is set, or a writer falls behind with the fail policy
+///
+/// The batch meeting a stop condition is cut short so that exactly the scheduled samples are
+/// written. When stopped the samples the device has already acquired are read and queued
+/// before the task is stopped. Read errors are reported, reading goes on after a buffer
+/// overrun and ends on any other error.
+fn acquire(mut task : DAQVTask, queues : Vec<OutputQueue>, schedule : &Schedule, stop : &AtomicUsize) -> Report {
+    let mut errors = Vec::new();
+    if let Some(start_at) = schedule.start_at {
+        wait_until(start_at, stop);
+    }
+    for batch in task.batches().scheduled(schedule).until(stop) {
+        match batch {
+            Ok(batch) => match queue_batch(batch, &queues) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(err) => {
+                    errors.push(err);
+                    break;
+                }
+            },
+            Err(err) => {
+                let overrun = err.is_overrun();
+                errors.push(err.to_string());
+                if !overrun {
+                    break;
+                }
+            }
+        }
+    }
+    if let Err(err) = task.stop() {
+        errors.push(err.to_string());
+    }
+    errors.extend(task.take_errors().iter().map(ToString::to_string));
+
+    // Writers finish the batches already queued
+    for queue in &queues {
+        queue.close();
+    }
+    Report {
+        errors : errors,
+        warnings : task.take_warnings().iter().map(ToString::to_string).collect(),
+        overruns : task.overruns(),
+        samples_dropped : task.samples_dropped(),
+        stats : Vec::new()
+    }
+}
+
+/// Write batches to the sink until there are no more or the sink is done, the sink begins with
+/// the stream info of the first batch
+pub fn write_batches<B : Borrow<OwnedBatch>>(sink : &mut dyn Sink, batches : impl IntoIterator<Item = B>) -> io::Result<()> {
+    let mut begun = false;
+    for batch in batches {
+        let batch = batch.borrow();
+        if !begun {
+            sink.begin(batch.info())?;
+            begun = true;
+        }
+        let scans = batch.as_batch();
+        sink.write_batch(scans.timestamps(), scans.samples())?;
+        if sink.is_done() {
+            break;
+        }
+    }
+    sink.finish()
+}
+
+/// Outcome of an acquisition run by [`run`]
+pub struct Report {
+    /// Failures of the acquisition and of the writers, prefixed with the output path
+    pub errors : Vec<String>,
+    /// Driver warnings, the acquisition went on regardless
+    pub warnings : Vec<String>,
+    /// Buffer overruns the acquisition recovered from, each is also one of the errors
+    pub overruns : u32,
+    /// Samples per channel lost to buffer overruns
+    pub samples_dropped : u64,
+    /// Queue statistics of every output, in the order of the outputs
+    pub stats : Vec<QueueStats>
+}
+
+/// Acquire on one thread and write every output, given by its path and sink, on a thread of
+/// its own until the schedule ends, every writer is done or `stop` is set
+///
+/// Slow outputs don't hold up reading the device, batches wait for them in queues of
+/// `config.queue.capacity` batches. Store a nonzero value in `stop` to end the acquisition,
+/// the samples the device has acquired by then are still written. A configuration failing
+/// [`Config::validate`] is reported without acquiring.
+pub fn run(task : DAQVTask, outputs : Vec<(String, Box<dyn Sink>)>, config : &Config, stop : Arc<AtomicUsize>) -> Report {
+    let queue = &config.queue;
+    let queues = config.validate().and_then(|()| {
+        outputs.iter().map(|_| BoundedQueue::new(queue.capacity, queue.overflow).map(Arc::new)).collect::<Result<Vec<OutputQueue>, String>>()
+    });
+    let queues = match queues {
+        Ok(queues) => queues,
+        Err(err) => return Report { errors : vec![err], warnings : Vec::new(), overruns : 0, samples_dropped : 0, stats : Vec::new() },
+    };
+    let mut writers = Vec::new();
+    for ((path, mut sink), output_queue) in outputs.into_iter().zip(queues.iter().cloned()) {
+        writers.push(thread::spawn(move || {
+            let result = write_batches(sink.as_mut(), std::iter::from_fn(|| output_queue.pop()));
+            // Stop queueing batches for this output
+            output_queue.close();
+            result.map_err(|err| format!("{}: {}", path, err))
+        }));
+    }
+
+    let acquisition = {
+        let queues = queues.clone();
+        let schedule = config.schedule.clone();
+        thread::spawn(move || acquire(task, queues, &schedule, &stop))
+    };
+    let mut report = acquisition.join().unwrap_or_else(|_| Report {
+        errors : vec![String::from("Acquisition thread panicked")],
+        warnings : Vec::new(),
+        overruns : 0,
+        samples_dropped : 0,
+        stats : Vec::new()
+    });
+    for result in writers.into_iter().map(|thread| thread.join()) {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => report.errors.push(err),
+            Err(_) => report.errors.push(String::from("Writer thread panicked")),
+        }
+    }
+    report.stats = queues.iter().map(|queue| queue.stats()).collect();
+    report
+}
+
+/// Write a recording to the sink, `speed` times as fast as it was acquired or as fast as
+/// possible if `speed` is 0, until its end, `stop` is set, or the sink is done
+pub fn replay(recording : &mut dyn Recording, sink : &mut dyn Sink, speed : f64, stop : &AtomicUsize) -> io::Result<()> {
+    let info = recording.info().clone();
+    sink.begin(&info)?;
+    let started = Local::now();
+    let mut timestamps = Vec::new();
+    let mut samples = Vec::new();
+    while stop.load(Ordering::Relaxed) == 0 && recording.read_batch(&mut timestamps, &mut samples)? {
+        // Like an acquisition, a batch is available once its last scan has been taken
+        if let (true, Some(last)) = (speed > 0.0, timestamps.last()) {
+            let elapsed = (*last - info.epoch).num_nanoseconds().unwrap_or(i64::MAX) as f64/speed;
+            wait_until(started + TimeDelta::nanoseconds(elapsed as i64), stop);
+        }
+        sink.write_batch(&timestamps, &samples)?;
+        if sink.is_done() {
+            break;
+        }
+    }
+    sink.finish()
+}
@@ -0,0 +1,227 @@
+//! Bounded queue handing acquired batches from the acquisition thread to a writer thread
+
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// What the acquisition does when a writer falls behind and its queue is full
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Wait for the writer, the device buffer absorbs the delay until it overflows
+    #[default]
+    Block,
+    /// Discard the oldest queued batch to make room
+    DropOldest,
+    /// Stop the acquisition
+    Fail
+}
+
+/// Why a batch could not be queued
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PushError {
+    /// The queue is full and the policy is to fail
+    Full,
+    /// The writer has stopped taking batches
+    Closed
+}
+
+/// Queue usage over the whole acquisition
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct QueueStats {
+    /// Batches queued so far, including dropped ones
+    pub pushed : u64,
+    /// Batches discarded by the drop-oldest policy
+    pub dropped : u64,
+    /// Highest number of batches waiting at once
+    pub max_depth : usize,
+    /// Number of times the acquisition waited for the writer
+    pub blocked : u64
+}
+
+struct State<T> {
+    items : VecDeque<T>,
+    closed : bool,
+    stats : QueueStats
+}
+
+/// Multi-producer, single-consumer FIFO holding at most `capacity` items
+pub struct BoundedQueue<T> {
+    capacity : usize,
+    policy : OverflowPolicy,
+    state : Mutex<State<T>>,
+    /// Signalled when an item is added or the queue is closed
+    not_empty : Condvar,
+    /// Signalled when an item is taken or the queue is closed
+    not_full : Condvar
+}
+
+impl<T> BoundedQueue<T> {
+    /// Empty queue, `capacity` must be at least 1
+    pub fn new(capacity : usize, policy : OverflowPolicy) -> Result<BoundedQueue<T>, String> {
+        if capacity == 0 {
+            return Err(String::from("The output queue must hold at least one batch"));
+        }
+        Ok(BoundedQueue {
+            capacity : capacity,
+            policy : policy,
+            state : Mutex::new(State {
+                items : VecDeque::with_capacity(capacity),
+                closed : false,
+                stats : QueueStats::default()
+            }),
+            not_empty : Condvar::new(),
+            not_full : Condvar::new()
+        })
+    }
+
+    /// Add an item, applying the overflow policy if the queue is full
+    pub fn push(&self, item : T) -> Result<(), PushError> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Err(PushError::Closed);
+        }
+        if state.items.len() >= self.capacity {
+            match self.policy {
+                OverflowPolicy::Block => {
+                    state.stats.blocked += 1;
+                    while state.items.len() >= self.capacity && !state.closed {
+                        state = self.not_full.wait(state).unwrap();
+                    }
+                    if state.closed {
+                        return Err(PushError::Closed);
+                    }
+                }
+                OverflowPolicy::DropOldest => {
+                    state.items.pop_front();
+                    state.stats.dropped += 1;
+                }
+                OverflowPolicy::Fail => return Err(PushError::Full),
+            }
+        }
+        state.items.push_back(item);
+        state.stats.pushed += 1;
+        state.stats.max_depth = std::cmp::max(state.stats.max_depth, state.items.len());
+        self.not_empty.notify_one();
+        Ok(())
+    }
+
+    /// Take the oldest item, waiting for one to arrive. None once the queue is closed and empty
+    pub fn pop(&self) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(item) = state.items.pop_front() {
+                self.not_full.notify_one();
+                return Some(item);
+            }
+            if state.closed {
+                return None;
+            }
+            state = self.not_empty.wait(state).unwrap();
+        }
+    }
+
+    /// Stop accepting items, items already queued can still be taken
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.not_empty.notify_all();
+        self.not_full.notify_all();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.state.lock().unwrap().closed
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        self.state.lock().unwrap().stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Wait until the queue reports a blocked push
+    fn wait_blocked<T>(queue : &BoundedQueue<T>) {
+        while queue.stats().blocked == 0 {
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn block() {
+        let queue = Arc::new(BoundedQueue::new(2, OverflowPolicy::Block).unwrap());
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(3))
+        };
+        wait_blocked(&queue);
+        // The push completes once there is room, nothing is lost
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(producer.join().unwrap(), Ok(()));
+        queue.close();
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(3));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.stats(), QueueStats { pushed : 3, dropped : 0, max_depth : 2, blocked : 1 });
+    }
+
+    #[test]
+    fn block_until_closed() {
+        let queue = Arc::new(BoundedQueue::new(1, OverflowPolicy::Block).unwrap());
+        queue.push(1).unwrap();
+        let producer = {
+            let queue = queue.clone();
+            thread::spawn(move || queue.push(2))
+        };
+        wait_blocked(&queue);
+        queue.close();
+        assert_eq!(producer.join().unwrap(), Err(PushError::Closed));
+        assert_eq!(queue.pop(), Some(1));
+        assert_eq!(queue.pop(), None);
+        assert_eq!(queue.stats().pushed, 1);
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let queue = BoundedQueue::new(2, OverflowPolicy::DropOldest).unwrap();
+        for item in 1..=5 {
+            queue.push(item).unwrap();
+        }
+        assert_eq!(queue.pop(), Some(4));
+        queue.push(6).unwrap();
+        assert_eq!(queue.pop(), Some(5));
+        assert_eq!(queue.pop(), Some(6));
+        assert_eq!(queue.stats(), QueueStats { pushed : 6, dropped : 3, max_depth : 2, blocked : 0 });
+    }
+
+    #[test]
+    fn fail() {
+        let queue = BoundedQueue::new(2, OverflowPolicy::Fail).unwrap();
+        queue.push(1).unwrap();
+        queue.push(2).unwrap();
+        assert_eq!(queue.push(3), Err(PushError::Full));
+        assert_eq!(queue.pop(), Some(1));
+        queue.push(4).unwrap();
+        assert_eq!(queue.stats(), QueueStats { pushed : 3, dropped : 0, max_depth : 2, blocked : 0 });
+        queue.close();
+        assert!(queue.is_closed());
+        assert_eq!(queue.push(5), Err(PushError::Closed));
+        assert_eq!(queue.pop(), Some(2));
+        assert_eq!(queue.pop(), Some(4));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn capacity() {
+        assert!(BoundedQueue::<u32>::new(0, OverflowPolicy::Block).is_err());
+    }
+}
@@ -0,0 +1,253 @@
+//! Custom scales converting samples to engineering units
+
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+
+use crate::channel::Range;
+
+/// Conversion of a channel's samples to engineering units, applied to every sample after it
+/// has been read
+///
+/// In configuration files the scale is a table with the type of function and its parameters,
+/// e.g. `scale = { type = "linear", slope = 25.0, offset = -1.5, units = "bar" }`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ScaleTable", into = "ScaleTable")]
+pub struct Scale {
+    /// Unit of the scaled values
+    pub units : String,
+    /// Write the unscaled value in a column of its own next to the scaled one
+    pub keep_raw : bool,
+    pub function : ScaleFunction
+}
+
+/// Layout of a scale in configuration files
+#[derive(Serialize, Deserialize)]
+struct ScaleTable {
+    units : String,
+    #[serde(default)]
+    keep_raw : bool,
+    #[serde(flatten)]
+    function : ScaleFunction
+}
+
+impl TryFrom<ScaleTable> for Scale {
+    type Error = String;
+
+    fn try_from(table : ScaleTable) -> Result<Scale, String> {
+        table.function.check()?;
+        Ok(Scale {
+            units : table.units,
+            keep_raw : table.keep_raw,
+            function : table.function
+        })
+    }
+}
+
+impl From<Scale> for ScaleTable {
+    fn from(scale : Scale) -> ScaleTable {
+        ScaleTable {
+            units : scale.units,
+            keep_raw : scale.keep_raw,
+            function : scale.function
+        }
+    }
+}
+
+impl Scale {
+    /// Scaled value of a sample
+    pub fn apply(&self, x : f64) -> f64 {
+        self.function.apply(x)
+    }
+
+    /// Range of the scaled values for an input range, from its scaled end points
+    pub fn range(&self, range : Range) -> Range {
+        let (a, b) = (self.apply(range.min), self.apply(range.max));
+        Range { min : a.min(b), max : a.max(b) }
+    }
+}
+
+/// Function mapping a sample to its scaled value
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum ScaleFunction {
+    /// `slope*x + offset`
+    Linear {
+        slope : f64,
+        #[serde(default)]
+        offset : f64
+    },
+    /// Maps `prescaled_min..prescaled_max` linearly onto `scaled_min..scaled_max`
+    MapRange {
+        prescaled_min : f64,
+        prescaled_max : f64,
+        scaled_min : f64,
+        scaled_max : f64
+    },
+    /// `c[0] + c[1]*x + c[2]*x² + ...`
+    Polynomial {
+        coefficients : Vec<f64>
+    },
+    /// Linear interpolation between points of a calibration table, extrapolated from the
+    /// first and last two points outside the table
+    Table {
+        /// Unscaled values, strictly increasing
+        prescaled : Vec<f64>,
+        scaled : Vec<f64>
+    }
+}
+
+impl ScaleFunction {
+    /// Reject parameters the function can't be evaluated with
+    fn check(&self) -> Result<(), String> {
+        match self {
+            ScaleFunction::Linear { .. } => Ok(()),
+            ScaleFunction::MapRange { prescaled_min, prescaled_max, .. } => {
+                if prescaled_min == prescaled_max {
+                    return Err(String::from("map-range scale: prescaled_min and prescaled_max must differ"));
+                }
+                Ok(())
+            }
+            ScaleFunction::Polynomial { coefficients } => {
+                if coefficients.is_empty() {
+                    return Err(String::from("polynomial scale: at least one coefficient is required"));
+                }
+                Ok(())
+            }
+            ScaleFunction::Table { prescaled, scaled } => {
+                if prescaled.len() != scaled.len() {
+                    return Err(format!("table scale: {} prescaled values but {} scaled values", prescaled.len(), scaled.len()));
+                }
+                if prescaled.len() < 2 {
+                    return Err(String::from("table scale: at least two points are required"));
+                }
+                if prescaled.windows(2).any(|pair| pair[0].partial_cmp(&pair[1]) != Some(Ordering::Less)) {
+                    return Err(String::from("table scale: prescaled values must be strictly increasing"));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    pub fn apply(&self, x : f64) -> f64 {
+        match self {
+            ScaleFunction::Linear { slope, offset } => slope*x + offset,
+            ScaleFunction::MapRange { prescaled_min, prescaled_max, scaled_min, scaled_max } => {
+                scaled_min + (x - prescaled_min)*(scaled_max - scaled_min)/(prescaled_max - prescaled_min)
+            }
+            // Horner's method
+            ScaleFunction::Polynomial { coefficients } => coefficients.iter().rev().fold(0.0, |y, c| y*x + c),
+            ScaleFunction::Table { prescaled, scaled } => {
+                // Segment containing x, or the first or last one outside the table
+                let i = prescaled.partition_point(|&p| p < x).clamp(1, prescaled.len() - 1);
+                let (x0, x1, y0, y1) = (prescaled[i - 1], prescaled[i], scaled[i - 1], scaled[i]);
+                y0 + (x - x0)*(y1 - y0)/(x1 - x0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::parse_channel_list;
+    use crate::{Backend, Config, DAQVTask};
+
+    fn scale(function : ScaleFunction) -> Scale {
+        Scale { units : String::from("bar"), keep_raw : false, function : function }
+    }
+
+    fn assert_close(actual : f64, expected : f64) {
+        assert!((actual - expected).abs() < 1e-12, "{} instead of {}", actual, expected);
+    }
+
+    #[test]
+    fn linear() {
+        let linear = scale(ScaleFunction::Linear { slope : -2.5, offset : 1.0 });
+        assert_close(linear.apply(0.0), 1.0);
+        assert_close(linear.apply(2.0), -4.0);
+        // A negative slope swaps the ends of the range
+        assert_eq!(linear.range(Range { min : -1.0, max : 2.0 }), Range { min : -4.0, max : 3.5 });
+    }
+
+    #[test]
+    fn map_range() {
+        let map = scale(ScaleFunction::MapRange { prescaled_min : 4e-3, prescaled_max : 20e-3, scaled_min : 0.0, scaled_max : 100.0 });
+        assert_close(map.apply(4e-3), 0.0);
+        assert_close(map.apply(12e-3), 50.0);
+        assert_close(map.apply(24e-3), 125.0);
+        assert!(ScaleFunction::MapRange { prescaled_min : 1.0, prescaled_max : 1.0, scaled_min : 0.0, scaled_max : 1.0 }.check().is_err());
+    }
+
+    #[test]
+    fn polynomial() {
+        let polynomial = scale(ScaleFunction::Polynomial { coefficients : vec![1.0, -2.0, 0.5] });
+        assert_close(polynomial.apply(0.0), 1.0);
+        assert_close(polynomial.apply(2.0), -1.0);
+        assert_close(polynomial.apply(-4.0), 17.0);
+        // Only the end points are scaled, the minimum in between isn't found
+        assert_eq!(polynomial.range(Range { min : 0.0, max : 4.0 }), Range { min : 1.0, max : 1.0 });
+        assert!(ScaleFunction::Polynomial { coefficients : Vec::new() }.check().is_err());
+    }
+
+    #[test]
+    fn table() {
+        let table = ScaleFunction::Table { prescaled : vec![0.0, 1.0, 3.0], scaled : vec![10.0, 20.0, 0.0] };
+        assert!(table.check().is_ok());
+        assert_close(table.apply(0.5), 15.0);
+        assert_close(table.apply(1.0), 20.0);
+        assert_close(table.apply(2.0), 10.0);
+        // Extrapolated from the first and last segments
+        assert_close(table.apply(-1.0), 0.0);
+        assert_close(table.apply(4.0), -10.0);
+
+        assert!(ScaleFunction::Table { prescaled : vec![0.0, 1.0], scaled : vec![0.0] }.check().is_err());
+        assert!(ScaleFunction::Table { prescaled : vec![0.0], scaled : vec![0.0] }.check().is_err());
+        assert!(ScaleFunction::Table { prescaled : vec![0.0, 1.0, 1.0], scaled : vec![0.0, 1.0, 2.0] }.check().is_err());
+        assert!(ScaleFunction::Table { prescaled : vec![0.0, f64::NAN], scaled : vec![0.0, 1.0] }.check().is_err());
+    }
+
+    #[test]
+    fn configuration() {
+        let parsed : Scale = toml::from_str("type = \"linear\"\nslope = 25.0\nunits = \"bar\"\nkeep_raw = true").unwrap();
+        assert_eq!(parsed, Scale { units : String::from("bar"), keep_raw : true, function : ScaleFunction::Linear { slope : 25.0, offset : 0.0 } });
+        let parsed : Scale = toml::from_str("type = \"map-range\"\nprescaled_min = 0.0\nprescaled_max = 10.0\nscaled_min = 0.0\nscaled_max = 1.0\nunits = \"m\"").unwrap();
+        assert!(!parsed.keep_raw);
+        assert!(toml::from_str::<Scale>("type = \"polynomial\"\ncoefficients = []\nunits = \"m\"").is_err());
+        assert!(toml::from_str::<Scale>("type = \"cubic\"\nunits = \"m\"").is_err());
+        assert!(toml::from_str::<Scale>("type = \"linear\"\nslope = 1.0\nunits = \"m\"\ngain = 2.0").is_err());
+    }
+
+    #[test]
+    fn raw_column_follows_scaled_column() {
+        let mut config = Config {
+            backend : Backend::Simulated,
+            channels : parse_channel_list("pressure=cDAQSimMod1/ai0, cDAQSimMod1/ai1, level=cDAQSimMod1/ai2").unwrap(),
+            ..Config::default()
+        };
+        config.timing.rate = 10000.0;
+        config.timing.size = 20;
+        config.channels[0].scale = Some(Scale { units : String::from("bar"), keep_raw : true, function : ScaleFunction::Linear { slope : 10.0, offset : 1.0 } });
+        config.channels[2].scale = Some(scale(ScaleFunction::Linear { slope : 2.0, offset : 0.0 }));
+        let mut task = DAQVTask::from_config(&config).unwrap();
+        let batch = task.acquire_samples().unwrap();
+
+        let names : Vec<_> = batch.channels().iter().map(|channel| channel.name.as_str()).collect();
+        assert_eq!(names, ["pressure", "pressure_raw", "cDAQSimMod1/ai1", "level"]);
+        let units : Vec<_> = batch.channels().iter().map(|channel| channel.units.as_str()).collect();
+        assert_eq!(units, ["bar", "V", "V", "bar"]);
+        assert_eq!((batch.channels()[0].min, batch.channels()[0].max), (-99.0, 101.0));
+        assert_eq!((batch.channels()[1].min, batch.channels()[1].max), (-10.0, 10.0));
+
+        assert_eq!(batch.len(), 20);
+        for scan in 0..batch.len() {
+            let values = batch.scan(scan);
+            assert_close(values[0], 10.0*values[1] + 1.0);
+        }
+        let raw = batch.channel("pressure_raw").unwrap();
+        let pressure = batch.column(0);
+        for (raw, pressure) in raw.iter().zip(pressure) {
+            assert_close(*pressure, 10.0*raw + 1.0);
+        }
+    }
+}
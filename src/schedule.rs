@@ -0,0 +1,204 @@
+//! When an acquisition starts and ends
+
+use chrono::prelude::*;
+use chrono::TimeDelta;
+use serde::{Deserialize, Serialize};
+
+/// Scheduled start and stop conditions, the acquisition runs until stopped if there are none
+///
+/// When several stop conditions are given the acquisition ends with whichever is met first.
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Schedule {
+    /// Wait until this time before starting the acquisition
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_at : Option<DateTime<Local>>,
+    /// Time after the first sample at which the acquisition ends [s]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration : Option<f64>,
+    /// Number of samples per channel after which the acquisition ends [N]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_samples : Option<u64>,
+    /// End the acquisition at this time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub until : Option<DateTime<Local>>
+}
+
+impl Schedule {
+    pub fn is_empty(&self) -> bool {
+        *self == Schedule::default()
+    }
+
+    /// Reject conditions that end the acquisition before it starts
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(duration) = self.duration {
+            if duration.is_nan() || duration <= 0.0 {
+                return Err(format!("Duration must be greater than zero, got {} s", duration));
+            }
+        }
+        if self.total_samples == Some(0) {
+            return Err(String::from("Total samples must be greater than zero"));
+        }
+        if let (Some(start_at), Some(until)) = (self.start_at, self.until) {
+            if until <= start_at {
+                return Err(format!("End time {} is not after start time {}", until, start_at));
+            }
+        }
+        Ok(())
+    }
+
+    /// Time at which the acquisition ends when the first sample was taken at `epoch`
+    pub fn end(&self, epoch : DateTime<Local>) -> Option<DateTime<Local>> {
+        let after_duration = self.duration.map(|duration| epoch + TimeDelta::nanoseconds((duration*1e9).round() as i64));
+        match (after_duration, self.until) {
+            (Some(a), Some(b)) => Some(std::cmp::min(a, b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Parse a duration made of numbers with a `d`, `h`, `m` or `s` unit, e.g. `10m`, `1h30m` or
+/// `2.5s`. A plain number is in seconds
+pub fn parse_duration(s : &str) -> Result<f64, String> {
+    let s = s.trim();
+    if let Ok(seconds) = s.parse::<f64>() {
+        return Ok(seconds);
+    }
+    if s.is_empty() {
+        return Err(String::from("duration missing"));
+    }
+    let mut seconds = 0.0;
+    let mut number = String::new();
+    for c in s.chars() {
+        let unit = match c {
+            'd' => 86400.0,
+            'h' => 3600.0,
+            'm' => 60.0,
+            's' => 1.0,
+            '0'..='9' | '.' | ' ' => {
+                number.push(c);
+                continue;
+            }
+            _ => return Err(format!("invalid duration, expected units d, h, m or s: {}", s)),
+        };
+        let value : f64 = number.trim().parse().map_err(|_| format!("invalid duration: {}", s))?;
+        seconds += value*unit;
+        number.clear();
+    }
+    if !number.trim().is_empty() {
+        return Err(format!("invalid duration, unit missing after {}: {}", number.trim(), s));
+    }
+    Ok(seconds)
+}
+
+/// Parse a point in time: RFC 3339 (`2025-04-01T08:00:00+02:00`), a local date and time
+/// (`2025-04-01 08:00[:00]`) or a local time of day (`08:00[:00]`), which is the next time the
+/// clock shows it
+pub fn parse_time(s : &str) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Local));
+    }
+    let local = |naive : NaiveDateTime| Local.from_local_datetime(&naive).earliest().ok_or_else(|| format!("{} does not exist in the local time zone", s));
+    for format in ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(s, format) {
+            return local(naive);
+        }
+    }
+    for format in ["%H:%M:%S%.f", "%H:%M"] {
+        if let Ok(time) = NaiveTime::parse_from_str(s, format) {
+            let now = Local::now();
+            let today = local(now.date_naive().and_time(time))?;
+            if today > now {
+                return Ok(today);
+            }
+            return local(now.date_naive().succ_opt().ok_or_else(|| format!("invalid time: {}", s))?.and_time(time));
+        }
+    }
+    Err(format!("expected a date and time like 2025-04-01 08:00:00 or a time of day like 08:00, got {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("90"), Ok(90.0));
+        assert_eq!(parse_duration("2.5s"), Ok(2.5));
+        assert_eq!(parse_duration("10m"), Ok(600.0));
+        assert_eq!(parse_duration("1h30m"), Ok(5400.0));
+        assert_eq!(parse_duration(" 1d 2h "), Ok(93600.0));
+        assert_eq!(parse_duration("1m1m"), Ok(120.0));
+    }
+
+    #[test]
+    fn invalid_durations() {
+        for s in ["", "h", "10x", "1h30", "1.2.3s", "-5m"] {
+            assert!(parse_duration(s).is_err(), "{} accepted", s);
+        }
+        // A plain number parses, the schedule rejects what can't be waited for
+        let schedule = Schedule { duration : Some(parse_duration("-5").unwrap()), ..Schedule::default() };
+        assert!(schedule.check().is_err());
+        let schedule = Schedule { duration : Some(parse_duration("NaN").unwrap()), ..Schedule::default() };
+        assert!(schedule.check().is_err());
+    }
+
+    #[test]
+    fn times() {
+        let expected = DateTime::parse_from_rfc3339("2025-04-01T06:00:00Z").unwrap();
+        assert_eq!(parse_time("2025-04-01T08:00:00+02:00"), Ok(expected.with_timezone(&Local)));
+        let local = Local.with_ymd_and_hms(2025, 4, 1, 8, 0, 0).unwrap();
+        for s in ["2025-04-01 08:00:00", "2025-04-01T08:00:00", "2025-04-01 08:00", " 2025-04-01T08:00 "] {
+            assert_eq!(parse_time(s), Ok(local), "{}", s);
+        }
+        assert_eq!(parse_time("2025-04-01 08:00:00.250"), Ok(local + TimeDelta::milliseconds(250)));
+    }
+
+    #[test]
+    fn invalid_times() {
+        for s in ["", "tomorrow", "25:00", "08:00 pm", "2025-13-01 08:00", "2025-04-01"] {
+            assert!(parse_time(s).is_err(), "{} accepted", s);
+        }
+    }
+
+    #[test]
+    fn time_of_day_already_past() {
+        // A time of day that has passed today is the same time tomorrow
+        let now = Local::now();
+        let earlier = now - TimeDelta::minutes(1);
+        let start_at = parse_time(&earlier.format("%H:%M:%S").to_string()).unwrap();
+        assert!(start_at > now);
+        assert!(start_at - now <= TimeDelta::days(1));
+        let later = now + TimeDelta::minutes(2);
+        let start_at = parse_time(&later.format("%H:%M:%S").to_string()).unwrap();
+        assert!(start_at > now && start_at <= later);
+    }
+
+    #[test]
+    fn start_at_already_past() {
+        // A date in the past is kept, the acquisition starts right away
+        let start_at = parse_time("2000-01-01 00:00").unwrap();
+        assert!(start_at < Local::now());
+        let schedule = Schedule { start_at : Some(start_at), duration : Some(10.0), ..Schedule::default() };
+        assert!(schedule.check().is_ok());
+        // The end is counted from the first sample, not from the start time
+        let epoch = Local::now();
+        assert_eq!(schedule.end(epoch), Some(epoch + TimeDelta::seconds(10)));
+
+        let until = Schedule { start_at : Some(start_at), until : Some(start_at), ..Schedule::default() };
+        assert!(until.check().is_err());
+    }
+
+    #[test]
+    fn first_stop_condition_ends() {
+        let epoch = Local.with_ymd_and_hms(2025, 4, 1, 8, 0, 0).unwrap();
+        let until = epoch + TimeDelta::seconds(30);
+        let schedule = Schedule { duration : Some(60.0), until : Some(until), ..Schedule::default() };
+        assert_eq!(schedule.end(epoch), Some(until));
+        let schedule = Schedule { duration : Some(1.5), until : Some(until), ..Schedule::default() };
+        assert_eq!(schedule.end(epoch), Some(epoch + TimeDelta::milliseconds(1500)));
+        assert_eq!(Schedule::default().end(epoch), None);
+        assert!(Schedule { total_samples : Some(0), ..Schedule::default() }.check().is_err());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Sink, SinkFactory, StreamInfo};
+use crate::trigger::SoftwareTrigger;
+
+/// Events to capture and how much of the signal around them is kept
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Capture {
+    pub trigger : SoftwareTrigger,
+    /// Scans kept ahead of the trigger [N]
+    #[serde(default)]
+    pub pretrigger : u64,
+    /// Scans written from the trigger on [N]
+    #[serde(default = "default_posttrigger")]
+    pub posttrigger : u64,
+    /// Time after a capture during which the trigger is ignored [s]
+    #[serde(default)]
+    pub holdoff : f64,
+    /// Number of events after which capturing ends, unlimited if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_events : Option<u64>
+}
+
+fn default_posttrigger() -> u64 {
+    1000
+}
+
+impl Capture {
+    pub fn new(trigger : SoftwareTrigger) -> Capture {
+        Capture {
+            trigger : trigger,
+            pretrigger : 0,
+            posttrigger : default_posttrigger(),
+            holdoff : 0.0,
+            max_events : None
+        }
+    }
+}
+
+/// Capture in progress
+struct Event {
+    sink : Box<dyn Sink>,
+    /// Scans still to be written after the trigger
+    remaining : u64
+}
+
+/// Watches one channel with a software trigger and writes the scans around every event to a
+/// file of its own
+///
+/// File names are made from a chrono format pattern applied to the time of the trigger, with
+/// the event number appended, e.g. `event_%H%M%S.csv` gives `event_142501_0001.csv`. Existing
+/// files are never overwritten, the number is counted up until the name is free. Every
+/// file holds the pretrigger scans followed by the posttrigger scans, starting with the one
+/// that met the trigger condition.
+pub struct CaptureSink {
+    pattern : String,
+    capture : Capture,
+    open : SinkFactory,
+    info : Option<StreamInfo>,
+    /// Output column the trigger watches
+    column : usize,
+    /// Most recent scans, at most `pretrigger` of them
+    history_timestamps : VecDeque<DateTime<Local>>,
+    history : VecDeque<f64>,
+    /// Value of the watched column in the previous scan
+    previous : Option<f64>,
+    /// Scans seen so far
+    scan : u64,
+    /// First scan the trigger is evaluated on again after a capture
+    rearm_at : u64,
+    events : u64,
+    event : Option<Event>
+}
+
+impl CaptureSink {
+    pub fn new(pattern : &str, capture : Capture, open : SinkFactory) -> io::Result<CaptureSink> {
+        if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid file name pattern: {}", pattern)));
+        }
+        if capture.posttrigger == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "at least one posttrigger scan must be captured"));
+        }
+        if capture.holdoff.is_nan() || capture.holdoff < 0.0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "holdoff must not be negative"));
+        }
+        Ok(CaptureSink {
+            pattern : String::from(pattern),
+            capture : capture,
+            open : open,
+            info : None,
+            column : 0,
+            history_timestamps : VecDeque::new(),
+            history : VecDeque::new(),
+            previous : None,
+            scan : 0,
+            rearm_at : 0,
+            events : 0,
+            event : None
+        })
+    }
+
+    /// File name of event number `n` triggered at `timestamp`, the number is counted up past
+    /// files that already exist
+    fn file_name(&self, timestamp : &DateTime<Local>, n : u64) -> PathBuf {
+        let path = PathBuf::from(timestamp.format(&self.pattern).to_string());
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+        let mut n = n;
+        loop {
+            let candidate = path.with_file_name(format!("{}_{:04}{}", stem, n, extension));
+            if !candidate.exists() {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+
+    /// Keep a scan for the pretrigger part of the next capture
+    fn remember(&mut self, timestamp : DateTime<Local>, scan : &[f64]) {
+        if self.capture.pretrigger == 0 {
+            return;
+        }
+        if self.history_timestamps.len() as u64 == self.capture.pretrigger {
+            self.history_timestamps.pop_front();
+            self.history.drain(..scan.len());
+        }
+        self.history_timestamps.push_back(timestamp);
+        self.history.extend(scan);
+    }
+
+    /// Open the file of a new event and write the pretrigger scans to it
+    fn trigger(&mut self, timestamp : &DateTime<Local>) -> io::Result<()> {
+        // The file starts with its first scan rather than the start of the acquisition
+        let mut info = self.info.clone().ok_or_else(|| io::Error::other("begin must be called before write_batch"))?;
+        self.events += 1;
+        let path = self.file_name(timestamp, self.events);
+        let mut sink = (self.open)(&path)?;
+        info.epoch = self.history_timestamps.front().copied().unwrap_or(*timestamp);
+        sink.begin(&info)?;
+        if !self.history_timestamps.is_empty() {
+            sink.write_batch(self.history_timestamps.make_contiguous(), self.history.make_contiguous())?;
+        }
+
+        self.event = Some(Event { sink : sink, remaining : self.capture.posttrigger });
+        Ok(())
+    }
+
+    /// Close the file of the current event and wait for the holdoff to pass
+    fn end_event(&mut self) -> io::Result<()> {
+        if let Some(mut event) = self.event.take() {
+            event.sink.finish()?;
+            let sample_rate = self.info.as_ref().map(|info| info.sample_rate).unwrap_or(0.0);
+            self.rearm_at = self.scan + (self.capture.holdoff*sample_rate).round() as u64;
+        }
+        Ok(())
+    }
+}
+
+impl Sink for CaptureSink {
+    fn begin(&mut self, info : &StreamInfo) -> io::Result<()> {
+        let channel = self.capture.trigger.channel();
+        self.column = match info.channels.iter().position(|info| info.name == channel || info.physical == channel) {
+            Some(column) => column,
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("trigger channel {} is not acquired", channel))),
+        };
+        self.info = Some(info.clone());
+        Ok(())
+    }
+
+    fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()> {
+        let channels = self.info.as_ref().map(|info| info.channels.len()).unwrap_or(0);
+        let rows = timestamps.len();
+        let mut i = 0;
+        while i < rows {
+            // Write the posttrigger scans of the event being captured
+            if let Some(event) = &mut self.event {
+                let end = std::cmp::min(rows, i + event.remaining as usize);
+                event.sink.write_batch(&timestamps[i..end], &samples[i*channels..end*channels])?;
+                event.remaining -= (end - i) as u64;
+                let complete = event.remaining == 0;
+                for row in i..end {
+                    self.previous = Some(samples[row*channels + self.column]);
+                    self.remember(timestamps[row], &samples[row*channels..(row + 1)*channels]);
+                }
+                self.scan += (end - i) as u64;
+                i = end;
+                if complete {
+                    self.end_event()?;
+                }
+                continue;
+            }
+
+            let value = samples[i*channels + self.column];
+            let armed = self.scan >= self.rearm_at && self.capture.max_events.is_none_or(|max_events| self.events < max_events);
+            let fired = armed && self.capture.trigger.fires(self.previous, value);
+            if fired {
+                // The triggering scan is the first posttrigger scan
+                self.trigger(&timestamps[i])?;
+                continue;
+            }
+            self.previous = Some(value);
+            self.remember(timestamps[i], &samples[i*channels..(i + 1)*channels]);
+            self.scan += 1;
+            i += 1;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        // An event cut short by the end of the acquisition keeps what was captured
+        self.end_event()
+    }
+
+    fn is_done(&self) -> bool {
+        self.event.is_none() && self.capture.max_events.is_some_and(|max_events| self.events >= max_events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, File};
+    use std::sync::{Arc, Mutex};
+    use chrono::TimeDelta;
+    use crate::sink::ChannelInfo;
+    use crate::trigger::parse_software_trigger;
+
+    /// What a sink was given for one file
+    #[derive(Debug)]
+    struct Recorded {
+        path : PathBuf,
+        epoch : DateTime<Local>,
+        timestamps : Vec<DateTime<Local>>,
+        samples : Vec<f64>,
+        finished : bool
+    }
+
+    /// Sink keeping what it is given in memory, next to the empty file it creates
+    struct Recorder {
+        files : Arc<Mutex<Vec<Recorded>>>,
+        index : usize
+    }
+
+    impl Sink for Recorder {
+        fn begin(&mut self, info : &StreamInfo) -> io::Result<()> {
+            self.files.lock().unwrap()[self.index].epoch = info.epoch;
+            Ok(())
+        }
+
+        fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()> {
+            let mut files = self.files.lock().unwrap();
+            files[self.index].timestamps.extend_from_slice(timestamps);
+            files[self.index].samples.extend_from_slice(samples);
+            Ok(())
+        }
+
+        fn finish(&mut self) -> io::Result<()> {
+            self.files.lock().unwrap()[self.index].finished = true;
+            Ok(())
+        }
+    }
+
+    struct Fixture {
+        directory : PathBuf,
+        files : Arc<Mutex<Vec<Recorded>>>,
+        sink : CaptureSink,
+        timestamps : Vec<DateTime<Local>>,
+        /// Scans written so far
+        written : usize
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.directory);
+        }
+    }
+
+    /// Capture sink on channel `level` of a 1 kHz stream of two channels, with files in a
+    /// directory of their own
+    fn fixture(name : &str, capture : Capture) -> Fixture {
+        let directory = std::env::temp_dir().join(format!("daqlogger-capture-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&directory).unwrap();
+        let files = Arc::new(Mutex::new(Vec::new()));
+        let recorded = files.clone();
+        let open : SinkFactory = Box::new(move |path| {
+            File::create(path)?;
+            let mut files = recorded.lock().unwrap();
+            files.push(Recorded { path : path.to_path_buf(), epoch : DateTime::<Local>::MIN_UTC.into(), timestamps : Vec::new(), samples : Vec::new(), finished : false });
+            Ok(Box::new(Recorder { files : recorded.clone(), index : files.len() - 1 }))
+        });
+        let pattern = directory.join("event_%H%M%S.csv").to_string_lossy().into_owned();
+        let mut sink = CaptureSink::new(&pattern, capture, open).unwrap();
+
+        let channel = |name : &str| ChannelInfo { name : String::from(name), physical : format!("Dev1/{}", name), units : String::from("V"), min : -10.0, max : 10.0, terminal : None };
+        let epoch = Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let info = StreamInfo { channels : vec![channel("level"), channel("other")], sample_rate : 1000.0, batch_size : 3, epoch : epoch };
+        sink.begin(&info).unwrap();
+        let timestamps = (0..100).map(|i| epoch + TimeDelta::milliseconds(i)).collect();
+        Fixture { directory : directory, files : files, sink : sink, timestamps : timestamps, written : 0 }
+    }
+
+    /// Write the next `values` of the watched channel in batches of 3 scans, the other channel counts scans
+    fn write(fixture : &mut Fixture, values : &[f64]) {
+        let first = fixture.written;
+        let samples : Vec<f64> = values.iter().enumerate().flat_map(|(i, value)| [*value, (first + i) as f64]).collect();
+        for (timestamps, samples) in fixture.timestamps[first..first + values.len()].chunks(3).zip(samples.chunks(6)) {
+            fixture.sink.write_batch(timestamps, samples).unwrap();
+        }
+        fixture.written += values.len();
+    }
+
+    /// Scans written to each file, by their index in the stream
+    fn scans(fixture : &Fixture) -> Vec<Vec<usize>> {
+        fixture.files.lock().unwrap().iter().map(|file| file.samples.chunks(2).map(|scan| scan[1] as usize).collect()).collect()
+    }
+
+    #[test]
+    fn pre_and_posttrigger_scans() {
+        let mut capture = Capture::new(parse_software_trigger("edge:level:5").unwrap());
+        capture.pretrigger = 3;
+        capture.posttrigger = 4;
+        let mut fixture = fixture("history", capture);
+        let values : Vec<f64> = (0..12).map(f64::from).collect();
+        write(&mut fixture, &values);
+        fixture.sink.finish().unwrap();
+
+        assert_eq!(scans(&fixture), [[2, 3, 4, 5, 6, 7, 8]]);
+        let files = fixture.files.lock().unwrap();
+        assert!(files[0].finished);
+        assert_eq!(files[0].epoch, fixture.timestamps[2]);
+        assert_eq!(files[0].timestamps, &fixture.timestamps[2..9]);
+        assert_eq!(files[0].samples[..2], [2.0, 2.0]);
+    }
+
+    #[test]
+    fn pretrigger_scans_at_the_start() {
+        let mut capture = Capture::new(parse_software_trigger("edge:level:1").unwrap());
+        capture.pretrigger = 5;
+        capture.posttrigger = 2;
+        let mut fixture = fixture("start", capture);
+        write(&mut fixture, &[0.0, 1.0, 2.0, 3.0]);
+        fixture.sink.finish().unwrap();
+        // Only the scans seen before the trigger are written ahead of it
+        assert_eq!(scans(&fixture), [[0, 1, 2]]);
+        assert_eq!(fixture.files.lock().unwrap()[0].epoch, fixture.timestamps[0]);
+    }
+
+    #[test]
+    fn rearm_after_holdoff() {
+        let mut capture = Capture::new(parse_software_trigger("level:level:0.5").unwrap());
+        capture.posttrigger = 2;
+        capture.holdoff = 0.003;
+        let mut fixture = fixture("holdoff", capture);
+        write(&mut fixture, &[1.0; 11]);
+        // The last event is cut short by the end of the stream
+        assert!(!fixture.files.lock().unwrap()[2].finished);
+        fixture.sink.finish().unwrap();
+        assert_eq!(scans(&fixture), [vec![0, 1], vec![5, 6], vec![10]]);
+        assert!(fixture.files.lock().unwrap().iter().all(|file| file.finished));
+        assert!(!fixture.sink.is_done());
+    }
+
+    #[test]
+    fn max_events() {
+        let mut capture = Capture::new(parse_software_trigger("level:level:0.5").unwrap());
+        capture.posttrigger = 2;
+        capture.max_events = Some(2);
+        let mut fixture = fixture("max_events", capture);
+        write(&mut fixture, &[1.0; 3]);
+        assert!(!fixture.sink.is_done());
+        write(&mut fixture, &[1.0; 9]);
+        assert!(fixture.sink.is_done());
+        assert_eq!(scans(&fixture), [[0, 1], [2, 3]]);
+    }
+
+    #[test]
+    fn file_numbering() {
+        let capture = Capture::new(parse_software_trigger("edge:level:0.5").unwrap());
+        let mut fixture = fixture("numbering", Capture { posttrigger : 1, ..capture });
+        let name = |scan : usize, n : u32| fixture.directory.join(format!("event_{}_{:04}.csv", fixture.timestamps[scan].format("%H%M%S"), n));
+        // A file left from an earlier run takes the first number, events in the same second
+        // count on from the previous one
+        let taken = name(1, 1);
+        File::create(&taken).unwrap();
+        let expected = [name(1, 2), name(3, 3)];
+
+        write(&mut fixture, &[0.0, 1.0, 0.0, 1.0]);
+        fixture.sink.finish().unwrap();
+        let paths : Vec<_> = fixture.files.lock().unwrap().iter().map(|file| file.path.clone()).collect();
+        assert_eq!(paths, expected);
+        assert!(taken.exists());
+    }
+
+    #[test]
+    fn trigger_channel_must_be_acquired() {
+        let capture = Capture::new(parse_software_trigger("edge:missing:0.5").unwrap());
+        let open : SinkFactory = Box::new(|_| Err(io::Error::other("no file expected")));
+        let mut sink = CaptureSink::new("event.csv", capture, open).unwrap();
+        let info = StreamInfo { channels : Vec::new(), sample_rate : 1000.0, batch_size : 1, epoch : Local::now() };
+        assert!(sink.begin(&info).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{Array, ArrayRef, Float64Array, TimestampNanosecondArray};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::ipc::reader::FileReader;
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use chrono::prelude::*;
+use clap::ValueEnum;
+use parquet::arrow::ArrowWriter;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use crate::MeasurementMode;
+use super::{ChannelInfo, Recording, Sink, StreamInfo};
+
+/// Rows per Parquet row group, buffered in memory until written
+const ROW_GROUP_SIZE : usize = 65536;
+
+fn to_io<E : std::error::Error + Send + Sync + 'static>(err : E) -> io::Error {
+    io::Error::other(err)
+}
+
+/// Schema with a UTC timestamp column and one column per channel, the acquisition settings
+/// are recorded in the schema metadata and channel settings in the field metadata
+fn schema(info : &StreamInfo) -> Schema {
+    let mut fields = vec![Field::new("timestamp", DataType::Timestamp(TimeUnit::Nanosecond, Some(Arc::from("UTC"))), false)];
+    for channel in &info.channels {
+        let metadata = HashMap::from([
+            (String::from("physical_channel"), channel.physical.clone()),
+            (String::from("units"), channel.units.clone()),
+            (String::from("terminal_config"), channel.terminal_name()),
+            (String::from("range_min"), channel.min.to_string()),
+            (String::from("range_max"), channel.max.to_string())
+        ]);
+        fields.push(Field::new(&channel.name, DataType::Float64, false).with_metadata(metadata));
+    }
+
+    let physical : Vec<&str> = info.channels.iter().map(|channel| channel.physical.as_str()).collect();
+    let terminals : Vec<String> = info.channels.iter().map(|channel| channel.terminal_name()).collect();
+    let metadata = HashMap::from([
+        (String::from("daqlogger.channels"), physical.join(", ")),
+        (String::from("daqlogger.mode"), terminals.join(", ")),
+        (String::from("daqlogger.rate"), info.sample_rate.to_string()),
+        (String::from("daqlogger.size"), info.batch_size.to_string()),
+        (String::from("daqlogger.t0"), info.epoch.to_rfc3339())
+    ]);
+    Schema::new(fields).with_metadata(metadata)
+}
+
+fn invalid(message : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Description of the acquisition recorded in a schema made by `schema`
+fn stream_info(schema : &Schema) -> io::Result<StreamInfo> {
+    let setting = |key : &str| schema.metadata().get(key).ok_or_else(|| invalid(format!("no {} in the schema metadata, not written by daqlogger", key)));
+    let fields = schema.fields();
+    if !matches!(fields.first().map(|field| field.data_type()), Some(DataType::Timestamp(TimeUnit::Nanosecond, _))) {
+        return Err(invalid(String::from("the first column is not a nanosecond timestamp")));
+    }
+    let mut channels = Vec::new();
+    for field in &fields[1..] {
+        if *field.data_type() != DataType::Float64 {
+            return Err(invalid(format!("column {} is not a double", field.name())));
+        }
+        let metadata = field.metadata();
+        let range = |key : &str| metadata.get(key).and_then(|value| value.parse().ok()).unwrap_or(f64::NAN);
+        channels.push(ChannelInfo {
+            name : field.name().clone(),
+            physical : metadata.get("physical_channel").unwrap_or(field.name()).clone(),
+            units : metadata.get("units").cloned().unwrap_or_default(),
+            min : range("range_min"),
+            max : range("range_max"),
+            terminal : metadata.get("terminal_config").and_then(|terminal| MeasurementMode::from_str(terminal, true).ok())
+        });
+    }
+    Ok(StreamInfo {
+        channels : channels,
+        sample_rate : setting("daqlogger.rate")?.parse().map_err(to_io)?,
+        batch_size : setting("daqlogger.size")?.parse().map_err(to_io)?,
+        epoch : DateTime::parse_from_rfc3339(setting("daqlogger.t0")?).map_err(to_io)?.with_timezone(&Local)
+    })
+}
+
+/// Convert a batch of scans interleaved by scan to columns
+fn record_batch(schema : &SchemaRef, timestamps : &[DateTime<Local>], samples : &[f64]) -> Result<RecordBatch, ArrowError> {
+    let channels = schema.fields().len() - 1;
+    let nanoseconds : Vec<i64> = timestamps.iter().map(|timestamp| timestamp.timestamp_nanos_opt().unwrap_or(i64::MAX)).collect();
+    let mut columns : Vec<ArrayRef> = vec![Arc::new(TimestampNanosecondArray::from(nanoseconds).with_timezone("UTC"))];
+    for channel in 0..channels {
+        let values : Vec<f64> = samples.iter().skip(channel).step_by(channels).copied().collect();
+        columns.push(Arc::new(Float64Array::from(values)));
+    }
+    RecordBatch::try_new(schema.clone(), columns)
+}
+
+/// Writes batches to an Arrow IPC file, one record batch per acquisition batch
+pub struct ArrowSink {
+    /// Output file until the writer is created by `begin`
+    file : Option<File>,
+    schema : SchemaRef,
+    writer : Option<FileWriter<BufWriter<File>>>
+}
+
+impl ArrowSink {
+    pub fn create(path : &Path) -> io::Result<ArrowSink> {
+        Ok(ArrowSink {
+            file : Some(File::create(path)?),
+            schema : Arc::new(Schema::empty()),
+            writer : None
+        })
+    }
+}
+
+impl Sink for ArrowSink {
+    fn begin(&mut self, info : &StreamInfo) -> io::Result<()> {
+        self.schema = Arc::new(schema(info));
+        let file = BufWriter::new(self.file.take().expect("begin called twice"));
+        self.writer = Some(FileWriter::try_new(file, &self.schema).map_err(to_io)?);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()> {
+        let batch = record_batch(&self.schema, timestamps, samples).map_err(to_io)?;
+        let writer = self.writer.as_mut().expect("begin must be called before write_batch");
+        writer.write(&batch).map_err(to_io)?;
+        writer.flush().map_err(to_io)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.finish().map_err(to_io)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes batches to a Snappy compressed Parquet file
+///
+/// Rows are buffered into row groups of `ROW_GROUP_SIZE`, so a file is only complete after
+/// `finish` has written the footer.
+pub struct ParquetSink {
+    /// Output file until the writer is created by `begin`
+    file : Option<File>,
+    schema : SchemaRef,
+    writer : Option<ArrowWriter<File>>
+}
+
+impl ParquetSink {
+    pub fn create(path : &Path) -> io::Result<ParquetSink> {
+        Ok(ParquetSink {
+            file : Some(File::create(path)?),
+            schema : Arc::new(Schema::empty()),
+            writer : None
+        })
+    }
+}
+
+impl Sink for ParquetSink {
+    fn begin(&mut self, info : &StreamInfo) -> io::Result<()> {
+        self.schema = Arc::new(schema(info));
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(ROW_GROUP_SIZE)
+            .build();
+        let file = self.file.take().expect("begin called twice");
+        self.writer = Some(ArrowWriter::try_new(file, self.schema.clone(), Some(properties)).map_err(to_io)?);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()> {
+        let batch = record_batch(&self.schema, timestamps, samples).map_err(to_io)?;
+        let writer = self.writer.as_mut().expect("begin must be called before write_batch");
+        writer.write(&batch).map_err(to_io)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.close().map_err(to_io)?;
+        }
+        Ok(())
+    }
+
+    /// Row groups on disk plus the encoded size of the one being buffered
+    fn size(&self) -> Option<u64> {
+        self.writer.as_ref().map(|writer| (writer.bytes_written() + writer.in_progress_size()) as u64)
+    }
+}
+
+/// Reads back a file written by `ArrowSink` or `ParquetSink`
+pub struct ColumnarRecording {
+    info : StreamInfo,
+    batches : Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>>>
+}
+
+impl ColumnarRecording {
+    pub fn open_arrow(path : &Path) -> io::Result<ColumnarRecording> {
+        let reader = FileReader::try_new(BufReader::new(File::open(path)?), None).map_err(to_io)?;
+        Ok(ColumnarRecording {
+            info : stream_info(&reader.schema())?,
+            batches : Box::new(reader)
+        })
+    }
+
+    /// Read a Parquet file in batches of the recorded batch size
+    pub fn open_parquet(path : &Path) -> io::Result<ColumnarRecording> {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?).map_err(to_io)?;
+        let info = stream_info(builder.schema())?;
+        let reader = builder.with_batch_size(std::cmp::max(info.batch_size, 1) as usize).build().map_err(to_io)?;
+        Ok(ColumnarRecording {
+            info : info,
+            batches : Box::new(reader)
+        })
+    }
+}
+
+impl Recording for ColumnarRecording {
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    fn read_batch(&mut self, timestamps : &mut Vec<DateTime<Local>>, samples : &mut Vec<f64>) -> io::Result<bool> {
+        let batch = loop {
+            match self.batches.next() {
+                Some(batch) => {
+                    let batch = batch.map_err(to_io)?;
+                    if batch.num_rows() > 0 {
+                        break batch;
+                    }
+                }
+                None => return Ok(false),
+            }
+        };
+        let time = batch.column(0).as_any().downcast_ref::<TimestampNanosecondArray>().ok_or_else(|| invalid(String::from("the first column is not a nanosecond timestamp")))?;
+        let mut columns = Vec::new();
+        for column in &batch.columns()[1..] {
+            columns.push(column.as_any().downcast_ref::<Float64Array>().ok_or_else(|| invalid(String::from("channel column is not a double")))?);
+        }
+        if time.null_count() > 0 || columns.iter().any(|column| column.null_count() > 0) {
+            return Err(invalid(String::from("missing values")));
+        }
+
+        // Interleave the columns by scan again
+        timestamps.clear();
+        timestamps.extend(time.values().iter().map(|nanoseconds| Local.timestamp_nanos(*nanoseconds)));
+        samples.clear();
+        for row in 0..batch.num_rows() {
+            samples.extend(columns.iter().map(|column| column.value(row)));
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,565 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use chrono::prelude::*;
+use chrono::TimeDelta;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::{ChannelInfo, Recording, Sink, StreamInfo};
+
+/// Scans per batch read back from CSV, which doesn't record the batch size
+const READ_BATCH_SCANS : usize = 1000;
+
+/// How the timestamp column is written
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimestampFormat {
+    /// ISO-8601 local time with UTC offset, e.g. 2024-05-01T12:00:00.000000+02:00
+    #[value(name = "iso8601")]
+    ISO8601,
+    /// Seconds since the Unix epoch
+    Epoch,
+    /// Seconds since the first sample of the acquisition
+    Relative,
+    /// No timestamp column
+    None
+}
+
+/// Layout of CSV output
+#[derive(Clone, Debug)]
+pub struct CsvFormat {
+    pub timestamp : TimestampFormat,
+    pub delimiter : char,
+    /// Digits after the decimal point, shortest exact representation if None
+    pub precision : Option<usize>,
+    /// Record the acquisition in `#` comment lines above the header
+    pub metadata : bool
+}
+
+/// Writes samples as delimited text, one row per scan under a header naming each channel
+///
+/// With `metadata` the time of the first sample, the sample rate, the delimiter and the input
+/// ranges are recorded in `#` comment lines above the header, otherwise the output is plain CSV.
+pub struct CsvSink {
+    out : Box<dyn Write + Send>,
+    format : CsvFormat,
+    channels : usize,
+    epoch : DateTime<Local>,
+    /// Row being assembled, reused between rows
+    line : String,
+    /// Number of fields in `line`
+    fields : usize
+}
+
+impl CsvSink {
+    pub fn new(out : Box<dyn Write + Send>, format : CsvFormat) -> CsvSink {
+        CsvSink {
+            out : out,
+            format : format,
+            channels : 0,
+            epoch : Local::now(),
+            line : String::new(),
+            fields : 0
+        }
+    }
+
+    /// Write to a file, or to stdout if `path` is "-"
+    pub fn create(path : &Path, format : CsvFormat) -> io::Result<CsvSink> {
+        let out : Box<dyn Write + Send> = if path == Path::new("-") {
+            Box::new(BufWriter::new(io::stdout()))
+        } else {
+            Box::new(BufWriter::new(File::create(path)?))
+        };
+        Ok(CsvSink::new(out, format))
+    }
+
+    /// Append a field to the current line, quoting it if it contains the delimiter, a quote or a line break
+    fn push_field(&mut self, field : &str) {
+        if self.fields > 0 {
+            self.line.push(self.format.delimiter);
+        }
+        self.fields += 1;
+        let delimiter = self.format.delimiter;
+        if field.contains([delimiter, '"', '\n', '\r']) {
+            self.line.push('"');
+            self.line.push_str(&field.replace('"', "\"\""));
+            self.line.push('"');
+        } else {
+            self.line.push_str(field);
+        }
+    }
+
+    /// Write the current line and start a new one
+    fn end_line(&mut self) -> io::Result<()> {
+        self.line.push('\n');
+        self.out.write_all(self.line.as_bytes())?;
+        self.line.clear();
+        self.fields = 0;
+        Ok(())
+    }
+
+    fn format_timestamp(&self, timestamp : &DateTime<Local>) -> String {
+        match self.format.timestamp {
+            TimestampFormat::ISO8601 => timestamp.format("%Y-%m-%dT%H:%M:%S%.6f%:z").to_string(),
+            TimestampFormat::Epoch => format!("{:.6}", timestamp.timestamp() as f64 + timestamp.timestamp_subsec_nanos() as f64*1e-9),
+            TimestampFormat::Relative => {
+                let elapsed = *timestamp - self.epoch;
+                format!("{:.6}", elapsed.num_nanoseconds().unwrap_or(i64::MAX) as f64*1e-9)
+            }
+            TimestampFormat::None => String::new(),
+        }
+    }
+
+    fn format_value(&self, value : f64) -> String {
+        match self.format.precision {
+            Some(precision) => format!("{:.*}", precision, value),
+            None => format!("{}", value),
+        }
+    }
+}
+
+impl Sink for CsvSink {
+    fn begin(&mut self, info : &StreamInfo) -> io::Result<()> {
+        self.channels = info.channels.len();
+        self.epoch = info.epoch;
+
+        if self.format.metadata {
+            writeln!(self.out, "# t0: {}", info.epoch.to_rfc3339())?;
+            writeln!(self.out, "# rate: {}", info.sample_rate)?;
+            writeln!(self.out, "# delimiter: {:?}", self.format.delimiter)?;
+            // One "MIN:MAX UNITS" field per channel, in the order of the header
+            self.line.push_str("# range: ");
+            for channel in &info.channels {
+                let range = format!("{}:{} {}", channel.min, channel.max, channel.units);
+                self.push_field(&range);
+            }
+            self.end_line()?;
+        }
+        match self.format.timestamp {
+            TimestampFormat::ISO8601 => self.push_field("timestamp"),
+            TimestampFormat::Epoch => self.push_field("epoch [s]"),
+            TimestampFormat::Relative => self.push_field("time [s]"),
+            TimestampFormat::None => {}
+        }
+        for channel in &info.channels {
+            self.push_field(&channel.name);
+        }
+        self.end_line()
+    }
+
+    fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()> {
+        for (row, timestamp) in timestamps.iter().enumerate() {
+            if self.format.timestamp != TimestampFormat::None {
+                let timestamp = self.format_timestamp(timestamp);
+                self.push_field(&timestamp);
+            }
+            for value in &samples[row*self.channels..(row + 1)*self.channels] {
+                let value = self.format_value(*value);
+                self.push_field(&value);
+            }
+            self.end_line()?;
+        }
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn invalid(message : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Split a line into fields, undoing the quoting of `CsvSink`
+fn split_fields(line : &str, delimiter : char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        if quoted {
+            if c != '"' {
+                field.push(c);
+            } else if chars.peek() == Some(&'"') {
+                field.push('"');
+                chars.next();
+            } else {
+                quoted = false;
+            }
+        } else if c == '"' {
+            quoted = true;
+        } else if c == delimiter {
+            fields.push(std::mem::take(&mut field));
+        } else {
+            field.push(c);
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Delimiter written as a Rust char literal by `CsvSink`, e.g. ',' or '\t'
+fn parse_delimiter(value : &str) -> Option<char> {
+    let literal = value.strip_prefix('\'')?.strip_suffix('\'')?;
+    match literal {
+        "\\t" => Some('\t'),
+        "\\'" => Some('\''),
+        "\\\\" => Some('\\'),
+        _ => {
+            let mut chars = literal.chars();
+            let delimiter = chars.next()?;
+            chars.next().is_none().then_some(delimiter)
+        }
+    }
+}
+
+/// Reads back a file written by `CsvSink`
+///
+/// The delimiter is taken from the `# delimiter` line, or given by the caller for plain CSV,
+/// and the timestamp format from the name of the timestamp column. Files without a `# rate`
+/// or `# t0` line take them from the timestamps of the first rows.
+pub struct CsvRecording {
+    lines : io::Lines<Box<dyn BufRead + Send>>,
+    info : StreamInfo,
+    delimiter : char,
+    timestamp : TimestampFormat,
+    /// Rows read ahead while working out the sample rate
+    ahead : VecDeque<String>,
+    /// Scans read so far
+    scans : u64,
+    line_number : usize
+}
+
+impl CsvRecording {
+    /// Read from a file, or from stdin if `path` is "-", with `delimiter` unless the file records one
+    pub fn open(path : &Path, delimiter : char) -> io::Result<CsvRecording> {
+        let input : Box<dyn BufRead + Send> = if path == Path::new("-") {
+            Box::new(BufReader::new(io::stdin()))
+        } else {
+            Box::new(BufReader::new(File::open(path)?))
+        };
+        CsvRecording::new(input, delimiter)
+    }
+
+    pub fn new(input : Box<dyn BufRead + Send>, delimiter : char) -> io::Result<CsvRecording> {
+        let mut lines = input.lines();
+        let mut line_number = 0;
+        let mut delimiter = delimiter;
+        let mut t0 = None;
+        let mut rate = None;
+        let mut ranges = None;
+        let header = loop {
+            let line = lines.next().ok_or_else(|| invalid(String::from("no header line")))??;
+            line_number += 1;
+            let comment = match line.strip_prefix('#') {
+                Some(comment) => comment.trim(),
+                None => break line,
+            };
+            if let Some(value) = comment.strip_prefix("t0:") {
+                let time = DateTime::parse_from_rfc3339(value.trim()).map_err(|err| invalid(format!("line {}: {}", line_number, err)))?;
+                t0 = Some(time.with_timezone(&Local));
+            } else if let Some(value) = comment.strip_prefix("rate:") {
+                rate = Some(value.trim().parse::<f64>().map_err(|err| invalid(format!("line {}: {}", line_number, err)))?);
+            } else if let Some(value) = comment.strip_prefix("delimiter:") {
+                delimiter = parse_delimiter(value.trim()).ok_or_else(|| invalid(format!("line {}: invalid delimiter {}", line_number, value.trim())))?;
+            } else if let Some(value) = comment.strip_prefix("range:") {
+                ranges = Some(String::from(value.trim_start()));
+            }
+        };
+
+        let mut names = split_fields(&header, delimiter);
+        let timestamp = match names[0].as_str() {
+            "timestamp" => TimestampFormat::ISO8601,
+            "epoch [s]" => TimestampFormat::Epoch,
+            "time [s]" => TimestampFormat::Relative,
+            _ => TimestampFormat::None,
+        };
+        if timestamp != TimestampFormat::None {
+            names.remove(0);
+        }
+
+        // Fields of the range line are "MIN:MAX UNITS", the input range is unknown without it
+        let entries = ranges.map_or(Vec::new(), |ranges| split_fields(&ranges, delimiter));
+        let count = names.len();
+        let channels = names.into_iter().enumerate().map(|(i, name)| {
+            let entry = entries.get(i).filter(|_| entries.len() == count);
+            let (range, units) = entry.map(|entry| entry.trim()).and_then(|entry| {
+                let (range, units) = entry.split_once(' ').unwrap_or((entry, ""));
+                let (min, max) = range.split_once(':')?;
+                Some(((min.parse().ok()?, max.parse().ok()?), String::from(units)))
+            }).unwrap_or(((f64::NAN, f64::NAN), String::new()));
+            ChannelInfo {
+                physical : name.clone(),
+                name : name,
+                units : units,
+                min : range.0,
+                max : range.1,
+                terminal : None
+            }
+        }).collect();
+
+        let mut recording = CsvRecording {
+            lines : lines,
+            info : StreamInfo {
+                channels : channels,
+                sample_rate : rate.unwrap_or(f64::NAN),
+                batch_size : READ_BATCH_SCANS as u64,
+                epoch : t0.unwrap_or_else(Local::now)
+            },
+            delimiter : delimiter,
+            timestamp : timestamp,
+            ahead : VecDeque::new(),
+            scans : 0,
+            line_number : line_number
+        };
+
+        // Work out what the comments don't say from the timestamps of the first two rows
+        if t0.is_none() || rate.is_none() {
+            while recording.ahead.len() < 2 {
+                match recording.lines.next() {
+                    Some(line) => recording.ahead.push_back(line?),
+                    None => break,
+                }
+            }
+            let absolute = timestamp == TimestampFormat::ISO8601 || timestamp == TimestampFormat::Epoch;
+            if t0.is_none() {
+                if !absolute || recording.ahead.is_empty() {
+                    return Err(invalid(String::from("time of the first sample unknown, no # t0 line")));
+                }
+                recording.info.epoch = recording.row_timestamp(&recording.ahead[0], line_number + 1)?;
+            }
+            if rate.is_none() {
+                if timestamp == TimestampFormat::None || recording.ahead.len() < 2 {
+                    return Err(invalid(String::from("sample rate unknown, no # rate line")));
+                }
+                let first = recording.row_timestamp(&recording.ahead[0], line_number + 1)?;
+                let second = recording.row_timestamp(&recording.ahead[1], line_number + 2)?;
+                let period = (second - first).num_nanoseconds().unwrap_or(0) as f64*1e-9;
+                if period.is_nan() || period <= 0.0 {
+                    return Err(invalid(String::from("sample rate unknown, no # rate line and the first timestamps don't increase")));
+                }
+                recording.info.sample_rate = 1.0/period;
+            }
+        }
+        Ok(recording)
+    }
+
+    /// Timestamp in the first field of a row
+    fn row_timestamp(&self, line : &str, line_number : usize) -> io::Result<DateTime<Local>> {
+        let field = split_fields(line, self.delimiter).swap_remove(0);
+        self.parse_timestamp(field.trim()).ok_or_else(|| invalid(format!("line {}: invalid timestamp {}", line_number, field)))
+    }
+
+    fn parse_timestamp(&self, field : &str) -> Option<DateTime<Local>> {
+        match self.timestamp {
+            TimestampFormat::ISO8601 => DateTime::parse_from_rfc3339(field).ok().map(|time| time.with_timezone(&Local)),
+            TimestampFormat::Epoch => field.parse::<f64>().ok().map(|seconds| Local.timestamp_nanos((seconds*1e9).round() as i64)),
+            TimestampFormat::Relative => field.parse::<f64>().ok().map(|seconds| self.info.epoch + TimeDelta::nanoseconds((seconds*1e9).round() as i64)),
+            TimestampFormat::None => None,
+        }
+    }
+
+    /// Append the scan in one row
+    fn parse_row(&self, line : &str, timestamps : &mut Vec<DateTime<Local>>, samples : &mut Vec<f64>) -> io::Result<()> {
+        let fields = split_fields(line, self.delimiter);
+        let channels = self.info.channels.len();
+        let values = match self.timestamp {
+            TimestampFormat::None => {
+                let offset = TimeDelta::nanoseconds(((self.scans + timestamps.len() as u64) as f64*1e9/self.info.sample_rate).round() as i64);
+                timestamps.push(self.info.epoch + offset);
+                &fields[..]
+            }
+            _ => {
+                let timestamp = self.parse_timestamp(fields[0].trim());
+                timestamps.push(timestamp.ok_or_else(|| invalid(format!("line {}: invalid timestamp {}", self.line_number, fields[0])))?);
+                &fields[1..]
+            }
+        };
+        if values.len() != channels {
+            return Err(invalid(format!("line {}: {} values for {} channels", self.line_number, values.len(), channels)));
+        }
+        for value in values {
+            samples.push(value.trim().parse().map_err(|_| invalid(format!("line {}: invalid value {}", self.line_number, value)))?);
+        }
+        Ok(())
+    }
+}
+
+impl Recording for CsvRecording {
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    fn read_batch(&mut self, timestamps : &mut Vec<DateTime<Local>>, samples : &mut Vec<f64>) -> io::Result<bool> {
+        timestamps.clear();
+        samples.clear();
+        while timestamps.len() < READ_BATCH_SCANS {
+            let line = match self.ahead.pop_front() {
+                Some(line) => line,
+                None => match self.lines.next() {
+                    Some(line) => line?,
+                    None => break,
+                },
+            };
+            self.line_number += 1;
+            if !line.trim().is_empty() {
+                self.parse_row(&line, timestamps, samples)?;
+            }
+        }
+        self.scans += timestamps.len() as u64;
+        Ok(!timestamps.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Writer whose output the test reads back after the sink is done with it
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Buffer {
+        fn write(&mut self, buf : &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Buffer {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn channel(name : &str, units : &str, min : f64, max : f64) -> ChannelInfo {
+        ChannelInfo {
+            name : String::from(name),
+            physical : String::from("Dev1/ai0"),
+            units : String::from(units),
+            min : min,
+            max : max,
+            terminal : None
+        }
+    }
+
+    fn info() -> StreamInfo {
+        StreamInfo {
+            channels : vec![
+                channel("pressure, inlet", "V", -10.0, 10.0),
+                channel("the \"hot\" side", "deg C", 0.0, 100.5),
+                channel("a;b\tc", "", -0.5, 0.5)
+            ],
+            sample_rate : 250.0,
+            batch_size : 4,
+            epoch : Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+        }
+    }
+
+    fn format(timestamp : TimestampFormat, delimiter : char, metadata : bool) -> CsvFormat {
+        CsvFormat {
+            timestamp : timestamp,
+            delimiter : delimiter,
+            precision : None,
+            metadata : metadata
+        }
+    }
+
+    /// Write 5 scans of `info()` and return the text
+    fn write(format : CsvFormat) -> (String, Vec<DateTime<Local>>, Vec<f64>) {
+        let info = info();
+        let timestamps : Vec<_> = (0..5).map(|i| info.epoch + TimeDelta::milliseconds(4*i)).collect();
+        let samples : Vec<f64> = (0..15).map(|i| i as f64*0.25 - 1.0).collect();
+        let buffer = Buffer::default();
+        let mut sink = CsvSink::new(Box::new(buffer.clone()), format);
+        sink.begin(&info).unwrap();
+        sink.write_batch(&timestamps[..3], &samples[..9]).unwrap();
+        sink.write_batch(&timestamps[3..], &samples[9..]).unwrap();
+        sink.finish().unwrap();
+        (buffer.text(), timestamps, samples)
+    }
+
+    fn read_back(text : String, delimiter : char) -> (StreamInfo, Vec<DateTime<Local>>, Vec<f64>) {
+        let mut recording = CsvRecording::new(Box::new(io::Cursor::new(text.into_bytes())), delimiter).unwrap();
+        let info = recording.info().clone();
+        let (mut timestamps, mut samples) = (Vec::new(), Vec::new());
+        let (mut batch_timestamps, mut batch_samples) = (Vec::new(), Vec::new());
+        while recording.read_batch(&mut batch_timestamps, &mut batch_samples).unwrap() {
+            timestamps.extend_from_slice(&batch_timestamps);
+            samples.extend_from_slice(&batch_samples);
+        }
+        (info, timestamps, samples)
+    }
+
+    #[test]
+    fn plain_by_default() {
+        let (text, _, _) = write(format(TimestampFormat::ISO8601, ',', false));
+        assert!(!text.contains('#'));
+        assert!(text.starts_with("timestamp,\"pressure, inlet\",\"the \"\"hot\"\" side\",a;b\tc\n"));
+        assert_eq!(text.lines().count(), 6);
+    }
+
+    #[test]
+    fn quoting() {
+        let (text, _, _) = write(format(TimestampFormat::None, ';', false));
+        assert_eq!(text.lines().next().unwrap(), "pressure, inlet;\"the \"\"hot\"\" side\";\"a;b\tc\"");
+        assert_eq!(split_fields("\"a;b\";\"say \"\"hi\"\"\";;c", ';'), ["a;b", "say \"hi\"", "", "c"]);
+        assert_eq!(split_fields("\"line\nbreak\",x", ','), ["line\nbreak", "x"]);
+    }
+
+    #[test]
+    fn delimiter_literal() {
+        for delimiter in [',', ';', '\t', ' ', '|', '\'', '\\'] {
+            assert_eq!(parse_delimiter(&format!("{:?}", delimiter)), Some(delimiter));
+        }
+        assert_eq!(parse_delimiter("','x"), None);
+        assert_eq!(parse_delimiter(","), None);
+    }
+
+    #[test]
+    fn round_trip_with_metadata() {
+        for delimiter in [',', ';', '\t', ' '] {
+            let (text, timestamps, samples) = write(format(TimestampFormat::Relative, delimiter, true));
+            // The recorded delimiter wins over the one given to the reader
+            let (read_info, read_timestamps, read_samples) = read_back(text, '|');
+            let info = info();
+            assert_eq!(read_info.epoch, info.epoch);
+            assert_eq!(read_info.sample_rate, info.sample_rate);
+            for (read, written) in read_info.channels.iter().zip(&info.channels) {
+                assert_eq!(read.name, written.name);
+                assert_eq!(read.units, written.units);
+                assert_eq!((read.min, read.max), (written.min, written.max));
+            }
+            assert_eq!(read_timestamps, timestamps);
+            assert_eq!(read_samples, samples);
+        }
+    }
+
+    #[test]
+    fn round_trip_plain() {
+        let (text, timestamps, samples) = write(format(TimestampFormat::ISO8601, ';', false));
+        let (read_info, read_timestamps, read_samples) = read_back(text, ';');
+        let names : Vec<_> = read_info.channels.iter().map(|channel| channel.name.as_str()).collect();
+        assert_eq!(names, ["pressure, inlet", "the \"hot\" side", "a;b\tc"]);
+        assert!(read_info.channels[0].min.is_nan());
+        assert_eq!(read_info.epoch, info().epoch);
+        assert!((read_info.sample_rate - 250.0).abs() < 1e-6);
+        assert_eq!(read_timestamps, timestamps);
+        assert_eq!(read_samples, samples);
+    }
+
+    #[test]
+    fn plain_without_timestamps_is_rejected() {
+        let (text, _, _) = write(format(TimestampFormat::None, ',', false));
+        assert!(CsvRecording::new(Box::new(io::Cursor::new(text.into_bytes())), ',').is_err());
+    }
+}
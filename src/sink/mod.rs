@@ -0,0 +1,135 @@
+//! Outputs acquired batches are written to, and reading their files back
+
+mod capture;
+mod columnar;
+mod csv;
+mod rotating;
+mod tdms;
+
+pub use self::capture::{Capture, CaptureSink};
+pub use self::columnar::{ArrowSink, ColumnarRecording, ParquetSink};
+pub use self::csv::{CsvFormat, CsvRecording, CsvSink, TimestampFormat};
+pub use self::rotating::{Interval, RotatingSink, RotationPolicy, SinkFactory, parse_size};
+pub use self::tdms::{TdmsRecording, TdmsSink};
+
+use std::io;
+use std::path::Path;
+
+use chrono::prelude::*;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::MeasurementMode;
+
+/// File format of the output
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Delimited text
+    Csv,
+    /// NI Technical Data Management Streaming
+    Tdms,
+    /// Arrow IPC file
+    Arrow,
+    /// Apache Parquet
+    Parquet
+}
+
+impl OutputFormat {
+    /// Format of a file by its extension, None if the extension isn't one of ours
+    pub fn of(path : &Path) -> Option<OutputFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "csv" | "tsv" | "txt" => Some(OutputFormat::Csv),
+            "tdms" => Some(OutputFormat::Tdms),
+            "arrow" | "arrows" | "ipc" | "feather" => Some(OutputFormat::Arrow),
+            "parquet" | "pq" => Some(OutputFormat::Parquet),
+            _ => None,
+        }
+    }
+}
+
+/// Description of one channel in the output
+#[derive(Clone, Debug)]
+pub struct ChannelInfo {
+    /// Virtual channel name
+    pub name : String,
+    /// Physical channel the samples are acquired from
+    pub physical : String,
+    /// Unit of the samples
+    pub units : String,
+    /// Input range
+    pub min : f64,
+    pub max : f64,
+    /// Terminal configuration of voltage channels
+    pub terminal : Option<MeasurementMode>
+}
+
+impl ChannelInfo {
+    /// Terminal configuration as written to file metadata, empty if the channel has none
+    pub fn terminal_name(&self) -> String {
+        self.terminal.map(|terminal| format!("{:?}", terminal)).unwrap_or_default()
+    }
+}
+
+/// Description of the acquisition, known once the first batch has been read
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+    /// Channels, in the order samples are interleaved
+    pub channels : Vec<ChannelInfo>,
+    /// Sample rate [samples/sec]
+    pub sample_rate : f64,
+    /// Number of samples per channel in a batch
+    pub batch_size : u64,
+    /// Time of the first sample of the acquisition
+    pub epoch : DateTime<Local>
+}
+
+/// Destination for acquired samples
+pub trait Sink : Send {
+    /// Start the output, called once before the first batch
+    fn begin(&mut self, info : &StreamInfo) -> io::Result<()>;
+
+    /// Write a batch of scans, `samples` are interleaved by scan with one timestamp per scan
+    fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()>;
+
+    /// Flush and close the output
+    fn finish(&mut self) -> io::Result<()>;
+
+    /// The output takes no more samples, the acquisition can end once every output is done
+    fn is_done(&self) -> bool {
+        false
+    }
+
+    /// Size of the output so far [bytes], counting what is still buffered in memory. None if
+    /// that is the size of the file on disk
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+/// Samples read back from a file written by one of the sinks
+pub trait Recording {
+    /// Description of the recorded acquisition
+    fn info(&self) -> &StreamInfo;
+
+    /// Read the next batch of scans into `timestamps` and `samples`, replacing their contents.
+    /// False at the end of the recording
+    fn read_batch(&mut self, timestamps : &mut Vec<DateTime<Local>>, samples : &mut Vec<f64>) -> io::Result<bool>;
+}
+
+/// Open a recording in the format given by its extension, "-" reads CSV from stdin.
+/// `delimiter` separates the columns of CSV that doesn't record its delimiter
+pub fn open_recording(path : &Path, delimiter : char) -> io::Result<Box<dyn Recording>> {
+    if path == Path::new("-") {
+        return Ok(Box::new(CsvRecording::open(path, delimiter)?));
+    }
+    match OutputFormat::of(path) {
+        Some(OutputFormat::Csv) => Ok(Box::new(CsvRecording::open(path, delimiter)?)),
+        Some(OutputFormat::Tdms) => Ok(Box::new(TdmsRecording::open(path)?)),
+        Some(OutputFormat::Arrow) => Ok(Box::new(ColumnarRecording::open_arrow(path)?)),
+        Some(OutputFormat::Parquet) => Ok(Box::new(ColumnarRecording::open_parquet(path)?)),
+        None => Err(io::Error::new(io::ErrorKind::InvalidInput, "unknown file format, expected a .csv, .tdms, .arrow or .parquet file")),
+    }
+}
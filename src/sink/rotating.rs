@@ -0,0 +1,276 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::{Sink, StreamInfo};
+
+/// Wall clock period after which a new file is started
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Interval {
+    /// New file at the start of every hour
+    Hourly,
+    /// New file at midnight
+    Daily
+}
+
+impl Interval {
+    /// Period a timestamp falls in, equal for timestamps that belong in the same file
+    fn period(&self, timestamp : &DateTime<Local>) -> (NaiveDate, u32) {
+        match self {
+            Interval::Hourly => (timestamp.date_naive(), timestamp.hour()),
+            Interval::Daily => (timestamp.date_naive(), 0),
+        }
+    }
+}
+
+/// When to start a new file, whichever limit is reached first
+#[derive(Clone, Debug, Default)]
+pub struct RotationPolicy {
+    /// Maximum file size [bytes], checked between batches, data a sink still buffers counts
+    pub max_bytes : Option<u64>,
+    pub interval : Option<Interval>,
+    /// Maximum number of scans per file
+    pub max_samples : Option<u64>
+}
+
+/// Opens the sink that writes one output file
+pub type SinkFactory = Box<dyn FnMut(&Path) -> io::Result<Box<dyn Sink>> + Send>;
+
+/// Splits output into a series of files, each starting with its own header
+///
+/// File names are made from a chrono format pattern (e.g. `rig1_%Y%m%d_%H%M%S.csv`) applied
+/// to the time of the first scan in the file. A name that is already taken gets a `_N`
+/// suffix so that existing files are never overwritten.
+pub struct RotatingSink {
+    pattern : String,
+    policy : RotationPolicy,
+    /// Number of most recent files to keep, older files written by this sink are deleted
+    keep : Option<usize>,
+    open : SinkFactory,
+    info : Option<StreamInfo>,
+    current : Option<Box<dyn Sink>>,
+    path : PathBuf,
+    samples_in_file : u64,
+    period : (NaiveDate, u32),
+    files : VecDeque<PathBuf>
+}
+
+impl RotatingSink {
+    pub fn new(pattern : &str, policy : RotationPolicy, keep : Option<usize>, open : SinkFactory) -> io::Result<RotatingSink> {
+        if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("invalid file name pattern: {}", pattern)));
+        }
+        if policy.max_samples == Some(0) || policy.max_bytes == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "rotation limits must be greater than zero"));
+        }
+        if keep == Some(0) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "at least one file must be kept"));
+        }
+        Ok(RotatingSink {
+            pattern : String::from(pattern),
+            policy : policy,
+            keep : keep,
+            open : open,
+            info : None,
+            current : None,
+            path : PathBuf::new(),
+            samples_in_file : 0,
+            period : (NaiveDate::MIN, 0),
+            files : VecDeque::new()
+        })
+    }
+
+    /// File name for a file starting at `timestamp` that doesn't exist yet
+    fn file_name(&self, timestamp : &DateTime<Local>) -> PathBuf {
+        let path = PathBuf::from(timestamp.format(&self.pattern).to_string());
+        if !path.exists() {
+            return path;
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        let extension = path.extension().map(|extension| format!(".{}", extension.to_string_lossy())).unwrap_or_default();
+        let mut n = 1;
+        loop {
+            let candidate = path.with_file_name(format!("{}_{}{}", stem, n, extension));
+            if !candidate.exists() {
+                return candidate;
+            }
+            n += 1;
+        }
+    }
+
+    /// Whether a scan taken at `timestamp` has to go into a new file
+    fn needs_rotation(&self, timestamp : &DateTime<Local>) -> io::Result<bool> {
+        if self.current.is_none() {
+            return Ok(true);
+        }
+        if let Some(max_samples) = self.policy.max_samples {
+            if self.samples_in_file >= max_samples {
+                return Ok(true);
+            }
+        }
+        if let Some(interval) = self.policy.interval {
+            if interval.period(timestamp) != self.period {
+                return Ok(true);
+            }
+        }
+        if let Some(max_bytes) = self.policy.max_bytes {
+            // Buffering sinks write much later than they take the samples
+            let size = match self.current.as_ref().and_then(|sink| sink.size()) {
+                Some(size) => size,
+                None => fs::metadata(&self.path)?.len(),
+            };
+            if size >= max_bytes {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Close the current file and start a new one at `timestamp`
+    fn rotate(&mut self, timestamp : &DateTime<Local>) -> io::Result<()> {
+        if let Some(mut sink) = self.current.take() {
+            sink.finish()?;
+        }
+
+        // Each file starts with its first scan rather than the start of the acquisition
+        let mut info = self.info.clone().ok_or_else(|| io::Error::other("begin must be called before write_batch"))?;
+        info.epoch = *timestamp;
+        let path = self.file_name(timestamp);
+        let mut sink = (self.open)(&path)?;
+        sink.begin(&info)?;
+        self.current = Some(sink);
+        self.path = path.clone();
+        self.samples_in_file = 0;
+        if let Some(interval) = self.policy.interval {
+            self.period = interval.period(timestamp);
+        }
+
+        // Drop the oldest files past the retention limit
+        self.files.push_back(path);
+        if let Some(keep) = self.keep {
+            while self.files.len() > keep {
+                let oldest = self.files.pop_front().unwrap();
+                match fs::remove_file(&oldest) {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Sink for RotatingSink {
+    fn begin(&mut self, info : &StreamInfo) -> io::Result<()> {
+        // The first file is opened with the first batch, its name depends on the first timestamp
+        self.info = Some(info.clone());
+        Ok(())
+    }
+
+    fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()> {
+        let channels = self.info.as_ref().map(|info| info.channels.len()).unwrap_or(0);
+        let rows = timestamps.len();
+        let mut start = 0;
+        while start < rows {
+            if self.needs_rotation(&timestamps[start])? {
+                self.rotate(&timestamps[start])?;
+            }
+
+            // Split the batch where the sample count or the interval runs out
+            let mut end = rows;
+            if let Some(max_samples) = self.policy.max_samples {
+                end = std::cmp::min(end, start + (max_samples - self.samples_in_file) as usize);
+            }
+            if let Some(interval) = self.policy.interval {
+                if let Some(i) = (start..end).find(|&i| interval.period(&timestamps[i]) != self.period) {
+                    end = i;
+                }
+            }
+
+            let sink = self.current.as_mut().unwrap();
+            sink.write_batch(&timestamps[start..end], &samples[start*channels..end*channels])?;
+            self.samples_in_file += (end - start) as u64;
+            start = end;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(mut sink) = self.current.take() {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a size in bytes with an optional binary K, M or G suffix, e.g. `100M`
+pub fn parse_size(s : &str) -> Result<u64, String> {
+    let s = s.trim();
+    let s = s.strip_suffix(|c| c == 'B' || c == 'b').unwrap_or(s);
+    let (digits, multiplier) = match s.chars().last() {
+        Some('K') | Some('k') => (&s[..s.len() - 1], 1u64 << 10),
+        Some('M') | Some('m') => (&s[..s.len() - 1], 1u64 << 20),
+        Some('G') | Some('g') => (&s[..s.len() - 1], 1u64 << 30),
+        _ => (s, 1),
+    };
+    let value : u64 = digits.trim().parse().map_err(|_| format!("invalid size: {}", s))?;
+    value.checked_mul(multiplier).ok_or_else(|| format!("size too large: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::csv::{CsvFormat, CsvRecording, CsvSink, TimestampFormat};
+    use crate::sink::{ChannelInfo, Recording};
+    use chrono::TimeDelta;
+
+    #[test]
+    fn each_file_starts_at_its_first_scan() {
+        let directory = std::env::temp_dir().join(format!("daqlogger-rotating-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let pattern = directory.join("rig_%H%M%S.csv").to_string_lossy().into_owned();
+        let format = CsvFormat { timestamp : TimestampFormat::Relative, delimiter : ',', precision : None, metadata : true };
+        let policy = RotationPolicy { max_samples : Some(4), ..RotationPolicy::default() };
+        let open : SinkFactory = Box::new(move |path| Ok(Box::new(CsvSink::create(path, format.clone())?)));
+        let mut sink = RotatingSink::new(&pattern, policy, None, open).unwrap();
+
+        let epoch = Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let info = StreamInfo {
+            channels : vec![ChannelInfo { name : String::from("ai0"), physical : String::from("Dev1/ai0"), units : String::from("V"), min : -10.0, max : 10.0, terminal : None }],
+            sample_rate : 1.0,
+            batch_size : 3,
+            epoch : epoch
+        };
+        let timestamps : Vec<_> = (0..10).map(|i| epoch + TimeDelta::seconds(i)).collect();
+        let samples : Vec<f64> = (0..10).map(f64::from).collect();
+        assert!(sink.write_batch(&timestamps[..1], &samples[..1]).is_err());
+        sink.begin(&info).unwrap();
+        for (timestamps, samples) in timestamps.chunks(3).zip(samples.chunks(3)) {
+            sink.write_batch(timestamps, samples).unwrap();
+        }
+        sink.finish().unwrap();
+
+        // Files of 4, 4 and 2 scans, each with t0 at its first scan and relative times from there
+        for (i, first) in [0, 4, 8].into_iter().enumerate() {
+            let path = PathBuf::from(timestamps[first].format(&pattern).to_string());
+            let mut recording = CsvRecording::open(&path, ',').unwrap();
+            assert_eq!(recording.info().epoch, timestamps[first], "file {}", i);
+            let (mut read_timestamps, mut read_samples) = (Vec::new(), Vec::new());
+            assert!(recording.read_batch(&mut read_timestamps, &mut read_samples).unwrap());
+            let count = std::cmp::min(4, 10 - first);
+            assert_eq!(read_timestamps, &timestamps[first..first + count]);
+            assert_eq!(read_samples, &samples[first..first + count]);
+        }
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 3);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
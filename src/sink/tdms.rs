@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use chrono::prelude::*;
+use chrono::TimeDelta;
+use clap::ValueEnum;
+
+use crate::MeasurementMode;
+use super::{ChannelInfo, Recording, Sink, StreamInfo};
+
+/// Segment contains metadata
+const TOC_META_DATA : u32 = 1 << 1;
+/// Segment contains a new object list
+const TOC_NEW_OBJ_LIST : u32 = 1 << 2;
+/// Segment contains raw data
+const TOC_RAW_DATA : u32 = 1 << 3;
+/// Raw data of the channels is interleaved
+const TOC_INTERLEAVED_DATA : u32 = 1 << 5;
+/// Numbers in the segment are big-endian
+const TOC_BIG_ENDIAN : u32 = 1 << 6;
+
+/// TDMS 2.0 file format version
+const VERSION : u32 = 4713;
+/// Raw data index of an object without data
+const NO_RAW_DATA : u32 = 0xFFFF_FFFF;
+/// Raw data index of an object with the same data as in the previous segment
+const SAME_RAW_DATA : u32 = 0;
+
+const TYPE_I32 : u32 = 0x03;
+const TYPE_DOUBLE : u32 = 0x0A;
+const TYPE_STRING : u32 = 0x20;
+const TYPE_TIMESTAMP : u32 = 0x44;
+
+/// Name of the channel holding the timestamp of every scan
+const TIME_CHANNEL : &str = "Time";
+
+/// TDMS property value
+enum Value {
+    I32(i32),
+    Double(f64),
+    String(String),
+    Timestamp(DateTime<Local>)
+}
+
+fn put_u32(buffer : &mut Vec<u8>, value : u32) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_u64(buffer : &mut Vec<u8>, value : u64) {
+    buffer.extend_from_slice(&value.to_le_bytes());
+}
+
+fn put_string(buffer : &mut Vec<u8>, value : &str) {
+    put_u32(buffer, value.len() as u32);
+    buffer.extend_from_slice(value.as_bytes());
+}
+
+/// Origin of LabVIEW timestamps
+fn labview_epoch() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(1904, 1, 1, 0, 0, 0).unwrap()
+}
+
+/// LabVIEW timestamp: 64-bit binary fraction of a second, then seconds since 1904-01-01 UTC
+fn put_timestamp(buffer : &mut Vec<u8>, value : &DateTime<Local>) {
+    let since = value.with_timezone(&Utc) - labview_epoch();
+    let seconds = since.num_seconds();
+    let nanoseconds = (since - chrono::TimeDelta::seconds(seconds)).num_nanoseconds().unwrap_or(0);
+    let fraction = ((nanoseconds as u128) << 64)/1_000_000_000;
+    put_u64(buffer, fraction as u64);
+    buffer.extend_from_slice(&seconds.to_le_bytes());
+}
+
+fn put_property(buffer : &mut Vec<u8>, name : &str, value : &Value) {
+    put_string(buffer, name);
+    match value {
+        Value::I32(value) => {
+            put_u32(buffer, TYPE_I32);
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        Value::Double(value) => {
+            put_u32(buffer, TYPE_DOUBLE);
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        Value::String(value) => {
+            put_u32(buffer, TYPE_STRING);
+            put_string(buffer, value);
+        }
+        Value::Timestamp(value) => {
+            put_u32(buffer, TYPE_TIMESTAMP);
+            put_timestamp(buffer, value);
+        }
+    }
+}
+
+/// Raw data index for a one-dimensional array of `count` values of `data_type`
+fn put_raw_data_index(buffer : &mut Vec<u8>, data_type : u32, count : u64) {
+    put_u32(buffer, 20);
+    put_u32(buffer, data_type);
+    put_u32(buffer, 1);
+    put_u64(buffer, count);
+}
+
+/// Object path component, single quotes are escaped by doubling them
+fn quote(name : &str) -> String {
+    format!("'{}'", name.replace('\'', "''"))
+}
+
+/// Writes samples as a TDMS file readable by DIAdem, LabVIEW and npTDMS
+///
+/// All channels go into one group, preceded by a `Time` channel with the timestamp of every
+/// scan. Channels also carry the waveform properties (`wf_start_time`, `wf_increment`) of a
+/// gap-free acquisition. Every batch is written as one segment; metadata is only repeated
+/// when the number of samples per channel changes.
+pub struct TdmsSink {
+    out : BufWriter<File>,
+    group : String,
+    /// Object paths of the channels, time channel first
+    paths : Vec<String>,
+    /// Samples per channel in the previous segment, 0 before the first one
+    segment_size : u64,
+    name : String
+}
+
+impl TdmsSink {
+    /// Create a TDMS file writing channels into `group`
+    pub fn create(path : &Path, group : &str) -> io::Result<TdmsSink> {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().into_owned();
+        Ok(TdmsSink {
+            out : BufWriter::new(File::create(path)?),
+            group : String::from(group),
+            paths : Vec::new(),
+            segment_size : 0,
+            name : name
+        })
+    }
+
+    /// Write a segment: lead-in, then metadata and raw data
+    fn write_segment(&mut self, toc : u32, metadata : &[u8], raw_data : &[u8]) -> io::Result<()> {
+        let mut lead_in = Vec::with_capacity(28);
+        lead_in.extend_from_slice(b"TDSm");
+        put_u32(&mut lead_in, toc);
+        put_u32(&mut lead_in, VERSION);
+        // Offset of the next segment and of the raw data, counted from the end of the lead-in
+        put_u64(&mut lead_in, (metadata.len() + raw_data.len()) as u64);
+        put_u64(&mut lead_in, metadata.len() as u64);
+        self.out.write_all(&lead_in)?;
+        self.out.write_all(metadata)?;
+        self.out.write_all(raw_data)
+    }
+
+    /// Metadata declaring every channel's raw data index for segments of `count` scans
+    fn raw_data_indices(&self, count : u64) -> Vec<u8> {
+        let mut metadata = Vec::new();
+        put_u32(&mut metadata, self.paths.len() as u32);
+        for (i, path) in self.paths.iter().enumerate() {
+            put_string(&mut metadata, path);
+            put_raw_data_index(&mut metadata, if i == 0 { TYPE_TIMESTAMP } else { TYPE_DOUBLE }, count);
+            put_u32(&mut metadata, 0);
+        }
+        metadata
+    }
+}
+
+impl Sink for TdmsSink {
+    fn begin(&mut self, info : &StreamInfo) -> io::Result<()> {
+        let group = format!("/{}", quote(&self.group));
+        self.paths = vec![format!("{}/{}", group, quote(TIME_CHANNEL))];
+        self.paths.extend(info.channels.iter().map(|channel| format!("{}/{}", group, quote(&channel.name))));
+
+        // Declare the file, group and channels with their properties, raw data follows with the first batch
+        let mut metadata = Vec::new();
+        put_u32(&mut metadata, 2 + self.paths.len() as u32);
+
+        put_string(&mut metadata, "/");
+        put_u32(&mut metadata, NO_RAW_DATA);
+        put_u32(&mut metadata, 2);
+        put_property(&mut metadata, "name", &Value::String(self.name.clone()));
+        put_property(&mut metadata, "datetime", &Value::Timestamp(info.epoch));
+
+        put_string(&mut metadata, &group);
+        put_u32(&mut metadata, NO_RAW_DATA);
+        put_u32(&mut metadata, 2);
+        put_property(&mut metadata, "sample_rate", &Value::Double(info.sample_rate));
+        put_property(&mut metadata, "batch_size", &Value::I32(info.batch_size as i32));
+
+        put_string(&mut metadata, &self.paths[0]);
+        put_u32(&mut metadata, NO_RAW_DATA);
+        put_u32(&mut metadata, 1);
+        put_property(&mut metadata, "NI_ChannelName", &Value::String(String::from(TIME_CHANNEL)));
+
+        for (channel, path) in info.channels.iter().zip(&self.paths[1..]) {
+            let properties = [
+                ("NI_ChannelName", Value::String(channel.name.clone())),
+                ("physical_channel", Value::String(channel.physical.clone())),
+                ("terminal_config", Value::String(channel.terminal_name())),
+                ("sample_rate", Value::Double(info.sample_rate)),
+                ("range_min", Value::Double(channel.min)),
+                ("range_max", Value::Double(channel.max)),
+                ("unit_string", Value::String(channel.units.clone())),
+                ("wf_start_time", Value::Timestamp(info.epoch)),
+                ("wf_start_offset", Value::Double(0.0)),
+                ("wf_increment", Value::Double(1.0/info.sample_rate)),
+                ("wf_samples", Value::I32(info.batch_size as i32)),
+                ("wf_xname", Value::String(String::from("Time"))),
+                ("wf_xunit_string", Value::String(String::from("s")))
+            ];
+            put_string(&mut metadata, path);
+            put_u32(&mut metadata, NO_RAW_DATA);
+            put_u32(&mut metadata, properties.len() as u32);
+            for (name, value) in &properties {
+                put_property(&mut metadata, name, value);
+            }
+        }
+
+        self.write_segment(TOC_META_DATA | TOC_NEW_OBJ_LIST, &metadata, &[])
+    }
+
+    fn write_batch(&mut self, timestamps : &[DateTime<Local>], samples : &[f64]) -> io::Result<()> {
+        let count = timestamps.len();
+        if count == 0 {
+            return Ok(());
+        }
+        let channels = self.paths.len() - 1;
+
+        // Channel data is stored one channel after another, not interleaved
+        let mut raw_data = Vec::with_capacity(count*(16 + 8*channels));
+        for timestamp in timestamps {
+            put_timestamp(&mut raw_data, timestamp);
+        }
+        for channel in 0..channels {
+            for scan in 0..count {
+                raw_data.extend_from_slice(&samples[scan*channels + channel].to_le_bytes());
+            }
+        }
+
+        if count as u64 == self.segment_size {
+            self.write_segment(TOC_RAW_DATA, &[], &raw_data)?;
+        } else {
+            let metadata = self.raw_data_indices(count as u64);
+            self.write_segment(TOC_META_DATA | TOC_RAW_DATA, &metadata, &raw_data)?;
+            self.segment_size = count as u64;
+        }
+        self.out.flush()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+fn invalid(message : String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn get_u32(bytes : &[u8]) -> u32 {
+    u32::from_le_bytes(bytes[..4].try_into().unwrap())
+}
+
+fn get_u64(bytes : &[u8]) -> u64 {
+    u64::from_le_bytes(bytes[..8].try_into().unwrap())
+}
+
+fn get_timestamp(bytes : &[u8]) -> DateTime<Local> {
+    let fraction = get_u64(bytes) as u128;
+    let seconds = get_u64(&bytes[8..]) as i64;
+    let nanoseconds = ((fraction*1_000_000_000 + (1 << 63)) >> 64) as i64;
+    (labview_epoch() + TimeDelta::seconds(seconds) + TimeDelta::nanoseconds(nanoseconds)).with_timezone(&Local)
+}
+
+/// Size of one raw data value
+fn type_size(data_type : u32) -> io::Result<usize> {
+    match data_type {
+        TYPE_DOUBLE => Ok(8),
+        TYPE_TIMESTAMP => Ok(16),
+        _ => Err(invalid(format!("unsupported raw data type 0x{:X}", data_type))),
+    }
+}
+
+/// Reads little-endian values from a metadata block
+struct MetadataReader<'a> {
+    metadata : &'a [u8]
+}
+
+impl<'a> MetadataReader<'a> {
+    fn take(&mut self, count : usize) -> io::Result<&'a [u8]> {
+        if count > self.metadata.len() {
+            return Err(invalid(String::from("metadata truncated")));
+        }
+        let (taken, rest) = self.metadata.split_at(count);
+        self.metadata = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(get_u32(self.take(4)?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(get_u64(self.take(8)?))
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let length = self.u32()? as usize;
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|err| invalid(err.to_string()))
+    }
+
+    fn value(&mut self, data_type : u32) -> io::Result<Value> {
+        let bytes = |reader : &mut Self, count : usize| -> io::Result<[u8; 8]> {
+            let mut value = [0; 8];
+            value[..count].copy_from_slice(reader.take(count)?);
+            Ok(value)
+        };
+        // Other numeric types are kept as doubles, only the value matters to the reader
+        Ok(match data_type {
+            TYPE_I32 => Value::I32(i32::from_le_bytes(bytes(self, 4)?[..4].try_into().unwrap())),
+            TYPE_DOUBLE => Value::Double(f64::from_le_bytes(bytes(self, 8)?)),
+            TYPE_STRING => Value::String(self.string()?),
+            TYPE_TIMESTAMP => Value::Timestamp(get_timestamp(self.take(16)?)),
+            0x01 => Value::Double(bytes(self, 1)?[0] as i8 as f64),
+            0x02 => Value::Double(i16::from_le_bytes(bytes(self, 2)?[..2].try_into().unwrap()) as f64),
+            0x04 => Value::Double(i64::from_le_bytes(bytes(self, 8)?) as f64),
+            0x05 | 0x21 => Value::Double(bytes(self, 1)?[0] as f64),
+            0x06 => Value::Double(u16::from_le_bytes(bytes(self, 2)?[..2].try_into().unwrap()) as f64),
+            0x07 => Value::Double(get_u32(&bytes(self, 4)?) as f64),
+            0x08 => Value::Double(u64::from_le_bytes(bytes(self, 8)?) as f64),
+            0x09 => Value::Double(f32::from_le_bytes(bytes(self, 4)?[..4].try_into().unwrap()) as f64),
+            _ => return Err(invalid(format!("unsupported property type 0x{:X}", data_type))),
+        })
+    }
+}
+
+/// Raw data layout of an object: data type and values per chunk
+type RawDataIndex = (u32, u64);
+
+/// Path components with the quoting of `quote` undone
+fn split_path(path : &str) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut rest = path;
+    while let Some(quoted) = rest.strip_prefix("/'") {
+        let mut component = String::new();
+        let mut chars = quoted.char_indices().peekable();
+        rest = "";
+        while let Some((i, c)) = chars.next() {
+            if c != '\'' {
+                component.push(c);
+            } else if chars.peek().map(|(_, c)| *c) == Some('\'') {
+                component.push('\'');
+                chars.next();
+            } else {
+                rest = &quoted[i + 1..];
+                break;
+            }
+        }
+        components.push(component);
+    }
+    components
+}
+
+/// Reads back a file written by `TdmsSink`, one batch per segment
+///
+/// The channels are those of the first group, the `Time` channel holds the timestamps. Only
+/// little-endian, non-interleaved double and timestamp data is supported.
+pub struct TdmsRecording {
+    input : BufReader<File>,
+    info : StreamInfo,
+    /// Objects in the order their raw data is stored, with the index of those that have data
+    objects : Vec<(String, Option<RawDataIndex>)>,
+    properties : HashMap<String, HashMap<String, Value>>,
+    time_path : String,
+    channel_paths : Vec<String>,
+    /// Raw data of the segment read while opening the file
+    pending : Option<Vec<u8>>
+}
+
+impl TdmsRecording {
+    pub fn open(path : &Path) -> io::Result<TdmsRecording> {
+        let mut recording = TdmsRecording {
+            input : BufReader::new(File::open(path)?),
+            info : StreamInfo {
+                channels : Vec::new(),
+                sample_rate : 0.0,
+                batch_size : 0,
+                epoch : Local::now()
+            },
+            objects : Vec::new(),
+            properties : HashMap::new(),
+            time_path : String::new(),
+            channel_paths : Vec::new(),
+            pending : None
+        };
+        // The first segment declares the channels and their properties
+        recording.pending = Some(recording.read_segment()?.ok_or_else(|| invalid(String::from("empty TDMS file")))?);
+        recording.read_info()?;
+        Ok(recording)
+    }
+
+    /// Read the next segment, updating the objects from its metadata, and return its raw data.
+    /// None at the end of the file
+    fn read_segment(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut lead_in = [0; 28];
+        match self.input.read_exact(&mut lead_in) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        if &lead_in[..4] != b"TDSm" {
+            return Err(invalid(String::from("not a TDMS segment")));
+        }
+        let toc = get_u32(&lead_in[4..]);
+        if toc & (TOC_INTERLEAVED_DATA | TOC_BIG_ENDIAN) != 0 {
+            return Err(invalid(String::from("interleaved or big-endian TDMS data is not supported")));
+        }
+        let next_segment = get_u64(&lead_in[12..]);
+        let raw_data_offset = get_u64(&lead_in[20..]);
+
+        let mut metadata = vec![0; raw_data_offset as usize];
+        self.input.read_exact(&mut metadata)?;
+        if toc & TOC_META_DATA != 0 {
+            self.read_metadata(&metadata, toc & TOC_NEW_OBJ_LIST != 0)?;
+        }
+
+        // A segment the writer didn't get to finish runs to the end of the file
+        let mut raw_data = Vec::new();
+        if next_segment == u64::MAX {
+            self.input.read_to_end(&mut raw_data)?;
+        } else {
+            raw_data.resize(next_segment.saturating_sub(raw_data_offset) as usize, 0);
+            self.input.read_exact(&mut raw_data)?;
+        }
+        if toc & TOC_RAW_DATA == 0 {
+            raw_data.clear();
+        }
+        Ok(Some(raw_data))
+    }
+
+    fn read_metadata(&mut self, metadata : &[u8], new_object_list : bool) -> io::Result<()> {
+        let previous = if new_object_list { std::mem::take(&mut self.objects) } else { Vec::new() };
+        let mut reader = MetadataReader { metadata : metadata };
+        for _ in 0..reader.u32()? {
+            let path = reader.string()?;
+            let index = match reader.u32()? {
+                NO_RAW_DATA => None,
+                SAME_RAW_DATA => {
+                    let index = self.objects.iter().chain(&previous).find(|object| object.0 == path).and_then(|object| object.1);
+                    Some(index.ok_or_else(|| invalid(format!("{} refers to a previous raw data index it doesn't have", path)))?)
+                }
+                0x6912_0000 | 0x6913_0000 => return Err(invalid(String::from("DAQmx raw data is not supported"))),
+                _ => {
+                    let data_type = reader.u32()?;
+                    let _dimension = reader.u32()?;
+                    let count = reader.u64()?;
+                    if data_type == TYPE_STRING {
+                        reader.u64()?;
+                    }
+                    Some((data_type, count))
+                }
+            };
+            let properties = self.properties.entry(path.clone()).or_default();
+            for _ in 0..reader.u32()? {
+                let name = reader.string()?;
+                let data_type = reader.u32()?;
+                properties.insert(name, reader.value(data_type)?);
+            }
+            match self.objects.iter_mut().find(|object| object.0 == path) {
+                Some(object) => object.1 = index,
+                None => self.objects.push((path, index)),
+            }
+        }
+        Ok(())
+    }
+
+    fn property(&self, path : &str, name : &str) -> Option<&Value> {
+        self.properties.get(path)?.get(name)
+    }
+
+    fn number(&self, path : &str, name : &str) -> Option<f64> {
+        match self.property(path, name)? {
+            Value::I32(value) => Some(*value as f64),
+            Value::Double(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn string(&self, path : &str, name : &str) -> Option<String> {
+        match self.property(path, name)? {
+            Value::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+
+    /// Find the channels of the first group and describe the acquisition from their properties
+    fn read_info(&mut self) -> io::Result<()> {
+        let paths : Vec<(&String, Vec<String>)> = self.objects.iter().map(|object| (&object.0, split_path(&object.0))).collect();
+        let (group_path, group) = paths.iter().find(|(_, components)| components.len() == 1).ok_or_else(|| invalid(String::from("no group")))?;
+        let channels : Vec<&(&String, Vec<String>)> = paths.iter().filter(|(_, components)| components.len() == 2 && components[0] == group[0]).collect();
+        let time = channels.iter().find(|(_, components)| components[1] == TIME_CHANNEL).ok_or_else(|| invalid(format!("no {} channel, not written by daqlogger", TIME_CHANNEL)))?;
+        self.time_path = time.0.clone();
+        self.channel_paths = channels.iter().filter(|(path, _)| **path != self.time_path).map(|(path, _)| (*path).clone()).collect();
+
+        let mut infos = Vec::new();
+        for (path, components) in channels.iter().filter(|(path, _)| **path != self.time_path) {
+            let range = |name| self.number(path, name).unwrap_or(f64::NAN);
+            infos.push(ChannelInfo {
+                name : components[1].clone(),
+                physical : self.string(path, "physical_channel").unwrap_or_else(|| components[1].clone()),
+                units : self.string(path, "unit_string").unwrap_or_default(),
+                min : range("range_min"),
+                max : range("range_max"),
+                terminal : self.string(path, "terminal_config").and_then(|terminal| MeasurementMode::from_str(&terminal, true).ok())
+            });
+        }
+        let first = self.channel_paths.first().map(String::as_str).unwrap_or_default();
+        let increment = self.number(first, "wf_increment").map(|increment| 1.0/increment);
+        let sample_rate = self.number(group_path, "sample_rate").or(increment).ok_or_else(|| invalid(String::from("sample rate unknown")))?;
+        let epoch = match self.property("/", "datetime").or_else(|| self.property(first, "wf_start_time")) {
+            Some(Value::Timestamp(epoch)) => *epoch,
+            _ => return Err(invalid(String::from("time of the first sample unknown"))),
+        };
+        self.info = StreamInfo {
+            channels : infos,
+            sample_rate : sample_rate,
+            batch_size : self.number(group_path, "batch_size").unwrap_or(1000.0) as u64,
+            epoch : epoch
+        };
+        Ok(())
+    }
+}
+
+impl Recording for TdmsRecording {
+    fn info(&self) -> &StreamInfo {
+        &self.info
+    }
+
+    fn read_batch(&mut self, timestamps : &mut Vec<DateTime<Local>>, samples : &mut Vec<f64>) -> io::Result<bool> {
+        let raw_data = loop {
+            let raw_data = match self.pending.take() {
+                Some(raw_data) => raw_data,
+                None => match self.read_segment()? {
+                    Some(raw_data) => raw_data,
+                    None => return Ok(false),
+                },
+            };
+            if !raw_data.is_empty() {
+                break raw_data;
+            }
+        };
+
+        let mut layout = Vec::new();
+        for (path, index) in &self.objects {
+            if let Some((data_type, count)) = index {
+                layout.push((path, *data_type, *count as usize, type_size(*data_type)?));
+            }
+        }
+        let chunk_size : usize = layout.iter().map(|(_, _, count, size)| count*size).sum();
+        if chunk_size == 0 || raw_data.len() % chunk_size != 0 {
+            return Err(invalid(String::from("raw data doesn't match its index")));
+        }
+
+        let channels = self.info.channels.len();
+        timestamps.clear();
+        samples.clear();
+        for chunk in raw_data.chunks(chunk_size) {
+            let first_scan = timestamps.len();
+            let scans = layout.iter().find(|(path, ..)| **path == self.time_path).map_or(0, |(_, _, count, _)| *count);
+            samples.resize((first_scan + scans)*channels, 0.0);
+            let mut offset = 0;
+            for (path, data_type, count, size) in &layout {
+                let data = &chunk[offset..offset + count*size];
+                offset += count*size;
+                if **path == self.time_path {
+                    timestamps.extend(data.chunks(16).map(get_timestamp));
+                } else if let Some(channel) = self.channel_paths.iter().position(|channel| channel == *path) {
+                    if *data_type != TYPE_DOUBLE || *count != scans {
+                        return Err(invalid(format!("{} doesn't have one double per timestamp", path)));
+                    }
+                    for (scan, value) in data.chunks(8).enumerate() {
+                        samples[(first_scan + scan)*channels + channel] = f64::from_le_bytes(value.try_into().unwrap());
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn info() -> StreamInfo {
+        let channel = |name : &str, units : &str, min, max| ChannelInfo {
+            name : String::from(name),
+            physical : format!("Dev1/{}", name),
+            units : String::from(units),
+            min : min,
+            max : max,
+            terminal : Some(MeasurementMode::DIFF)
+        };
+        StreamInfo {
+            channels : vec![channel("ai0", "V", -10.0, 10.0), channel("it's hot", "deg C", 0.0, 150.0)],
+            sample_rate : 1000.0,
+            batch_size : 3,
+            epoch : Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+        }
+    }
+
+    fn temp_path(name : &str) -> PathBuf {
+        std::env::temp_dir().join(format!("daqlogger-tdms-{}-{}.tdms", std::process::id(), name))
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round_trip");
+        let info = info();
+        let mut sink = TdmsSink::create(&path, "Data").unwrap();
+        sink.begin(&info).unwrap();
+
+        // Batch sizes change between segments, so only some of them carry metadata
+        let sizes = [3, 3, 0, 2, 2, 5, 3];
+        let mut batches = Vec::new();
+        let mut scan = 0;
+        for size in sizes {
+            let timestamps : Vec<_> = (scan..scan + size).map(|i| info.epoch + TimeDelta::milliseconds(i)).collect();
+            let samples : Vec<f64> = (scan*2..(scan + size)*2).map(|i| i as f64*0.5 - 3.0).collect();
+            sink.write_batch(&timestamps, &samples).unwrap();
+            if size > 0 {
+                batches.push((timestamps, samples));
+            }
+            scan += size;
+        }
+        sink.finish().unwrap();
+
+        let mut recording = TdmsRecording::open(&path).unwrap();
+        let read_info = recording.info().clone();
+        assert_eq!(read_info.epoch, info.epoch);
+        assert_eq!(read_info.sample_rate, info.sample_rate);
+        assert_eq!(read_info.batch_size, info.batch_size);
+        for (read, written) in read_info.channels.iter().zip(&info.channels) {
+            assert_eq!(read.name, written.name);
+            assert_eq!(read.physical, written.physical);
+            assert_eq!(read.units, written.units);
+            assert_eq!((read.min, read.max), (written.min, written.max));
+            assert_eq!(read.terminal, written.terminal);
+        }
+        assert_eq!(read_info.channels.len(), info.channels.len());
+
+        let (mut timestamps, mut samples) = (Vec::new(), Vec::new());
+        for (written_timestamps, written_samples) in &batches {
+            assert!(recording.read_batch(&mut timestamps, &mut samples).unwrap());
+            assert_eq!(&timestamps, written_timestamps);
+            assert_eq!(&samples, written_samples);
+        }
+        assert!(!recording.read_batch(&mut timestamps, &mut samples).unwrap());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn no_batches() {
+        let path = temp_path("no_batches");
+        let info = info();
+        let mut sink = TdmsSink::create(&path, "Data").unwrap();
+        sink.begin(&info).unwrap();
+        sink.finish().unwrap();
+
+        let mut recording = TdmsRecording::open(&path).unwrap();
+        let names : Vec<_> = recording.info().channels.iter().map(|channel| channel.name.clone()).collect();
+        assert_eq!(names, ["ai0", "it's hot"]);
+        assert_eq!(recording.info().epoch, info.epoch);
+        let (mut timestamps, mut samples) = (Vec::new(), Vec::new());
+        assert!(!recording.read_batch(&mut timestamps, &mut samples).unwrap());
+        assert!(timestamps.is_empty() && samples.is_empty());
+        fs::remove_file(&path).unwrap();
+    }
+}
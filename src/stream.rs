@@ -0,0 +1,75 @@
+//! Batches as an async stream, read from the task by a thread of its own
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use futures_core::Stream;
+
+use crate::DAQVTask;
+use crate::batch::OwnedBatch;
+use crate::error::DAQmxError;
+use crate::schedule::Schedule;
+
+/// Stream of the batches of a task, ending with the same stop conditions as
+/// [`Batches`](crate::batch::Batches)
+///
+/// Reads block, so the task is read by a thread that stays up to `capacity` batches ahead of
+/// the consumer, then waits for it. Dropping the stream stops the task after the read in
+/// progress.
+pub struct BatchStream {
+    receiver : Receiver<Result<OwnedBatch, DAQmxError>>,
+    /// Woken when the reader has sent a batch or is done
+    waker : Arc<Mutex<Option<Waker>>>
+}
+
+impl BatchStream {
+    /// Start reading batches from the task until a stop condition of the schedule is met or
+    /// `stop` is nonzero. The start time of the schedule is not waited for
+    pub fn new(mut task : DAQVTask, schedule : Schedule, stop : Arc<AtomicUsize>, capacity : usize) -> BatchStream {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let waker = Arc::new(Mutex::new(None::<Waker>));
+        let reader_waker = waker.clone();
+        thread::spawn(move || {
+            let wake = || {
+                if let Some(waker) = reader_waker.lock().unwrap().take() {
+                    waker.wake();
+                }
+            };
+            for batch in task.batches().scheduled(&schedule).until(&stop) {
+                if sender.send(batch).is_err() {
+                    // Nobody is listening anymore
+                    break;
+                }
+                wake();
+            }
+            if let Err(err) = task.stop() {
+                let _ = sender.send(Err(err));
+            }
+            // The sender is dropped first so the consumer sees the end
+            drop(sender);
+            wake();
+        });
+        BatchStream {
+            receiver : receiver,
+            waker : waker
+        }
+    }
+}
+
+impl Stream for BatchStream {
+    type Item = Result<OwnedBatch, DAQmxError>;
+
+    fn poll_next(self : Pin<&mut Self>, cx : &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // Registered before looking, a batch sent in between is found by the look
+        *self.waker.lock().unwrap() = Some(cx.waker().clone());
+        match self.receiver.try_recv() {
+            Ok(batch) => Poll::Ready(Some(batch)),
+            Err(TryRecvError::Empty) => Poll::Pending,
+            Err(TryRecvError::Disconnected) => Poll::Ready(None),
+        }
+    }
+}
@@ -0,0 +1,544 @@
+//! Acquisition task reading batches of scans from a backend
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chrono::prelude::*;
+use chrono::TimeDelta;
+
+use crate::backend::{AcquisitionBackend, SampleMode};
+use crate::batch::{Batch, Batches};
+use crate::channel::{ChannelConfig, ChannelKind};
+use crate::config::Config;
+use crate::error::{DAQmxError, Error};
+use crate::scale::Scale;
+use crate::sink::{ChannelInfo, StreamInfo};
+use crate::trigger::TriggerConfig;
+
+/// Input buffer size in a continuous acquisition [batches]
+static BUFFER_BATCHES : u64 = 10;
+/// Read timeout [s]
+static READ_TIMEOUT : f64 = 10.0;
+/// Timeout of the reads a triggered task waits for its trigger with, a stop is noticed in
+/// between. Scans that arrive before a read times out are kept [s]
+static TRIGGER_WAIT : f64 = 0.5;
+
+/// Analog input task running on an acquisition backend
+///
+/// Every read fills the task's buffers with one batch of scans and returns a [`Batch`] borrowing
+/// them, [`last_batch`](DAQVTask::last_batch) gives it again until the next read.
+pub struct DAQVTask {
+    backend : Box<dyn AcquisitionBackend>,
+    samples : Vec<f64>,
+    timestamps : Vec<DateTime<Local>>,
+    channels : usize,
+    /// Scale of every virtual channel
+    scales : Vec<Option<Scale>>,
+    /// Scaled samples, with an extra column after every channel keeping its raw value
+    output : Vec<f64>,
+    /// Scaled samples by output column, each column one batch long
+    columns : Vec<f64>,
+    /// Output columns, one per channel plus one per kept raw value
+    channel_info : Vec<ChannelInfo>,
+    sample_rate : f64,
+    sample_count : u64,
+    sample_mode : SampleMode,
+    samples_read : usize,
+    /// Continuous acquisition has been started and not stopped by an overrun
+    running : bool,
+    /// Samples per channel read since the task was last started
+    read_since_start : u64,
+    /// Buffer overruns in the continuous acquisition so far
+    overruns : u32,
+    /// Samples per channel lost to buffer overruns so far
+    samples_dropped : u64,
+    /// Errors of calls cleaning up after another error, which was returned instead
+    errors : Vec<DAQmxError>,
+    /// Start or reference trigger configured, reads wait for it however long it takes
+    triggered : bool,
+    /// Started and waiting for the trigger, no samples read yet
+    awaiting_trigger : bool,
+    /// The time of the first sample is only known once the trigger has come and samples arrive
+    t0_pending : bool,
+    /// Time of the first sample since the task was last started
+    t0 : DateTime<Local>,
+    /// Time of the very first sample acquired by the task
+    epoch : Option<DateTime<Local>>
+}
+
+impl DAQVTask {
+    /// Create the channels on the backend and configure their timing and triggers, checking
+    /// ranges and terminal configurations against what the device supports
+    pub fn new(mut backend : Box<dyn AcquisitionBackend>, channels : &[ChannelConfig], sample_rate : f64, sample_mode : SampleMode, sample_count : u64, first_sample_timestamp : bool, triggers : &TriggerConfig) -> Result<DAQVTask, Error> {
+        if channels.is_empty() {
+            return Err(Error::NoChannels);
+        }
+        let mut channel_info = Vec::<ChannelInfo>::new();
+        let mut scales = Vec::<Option<Scale>>::new();
+        for channel in channels {
+            let range = channel.range();
+            if let ChannelKind::Voltage = channel.kind {
+                // Check the range before the driver silently picks a wider one or rejects it
+                let supported = backend.voltage_ranges(channel.device())?;
+                if !supported.is_empty() && !supported.iter().any(|supported| supported.contains(&range)) {
+                    return Err(Error::UnsupportedRange {
+                        channel : channel.physical.clone(),
+                        range : range,
+                        supported : supported
+                    });
+                }
+            }
+
+            if channel.kind.has_terminal() {
+                let supported = backend.terminal_configs(channel.first_physical())?;
+                if !supported.contains(&channel.terminal) {
+                    return Err(Error::UnsupportedTerminal {
+                        channel : channel.physical.clone(),
+                        terminal : channel.terminal,
+                        supported : supported
+                    });
+                }
+            }
+
+            // Create channels of the configured kind
+            let (physical, mode, min, max) = (&channel.physical, channel.terminal, range.min, range.max);
+            match &channel.kind {
+                ChannelKind::Voltage => backend.create_voltage_channels(physical, mode, min, max)?,
+                ChannelKind::Thermocouple(thermocouple) => backend.create_thermocouple_channels(physical, min, max, thermocouple)?,
+                ChannelKind::Rtd(rtd) => backend.create_rtd_channels(physical, min, max, rtd)?,
+                ChannelKind::Current(current) => backend.create_current_channels(physical, mode, min, max, current)?,
+                ChannelKind::Strain(strain) => backend.create_strain_channels(physical, min, max, strain)?,
+                ChannelKind::Bridge(bridge) => backend.create_bridge_channels(physical, min, max, bridge)?,
+                ChannelKind::Accelerometer(accelerometer) => backend.create_accelerometer_channels(physical, mode, min, max, accelerometer)?,
+            }
+
+            // Virtual channels created for this entry are named after their physical channels,
+            // the output uses the configured name instead if there is one
+            let names = backend.channel_names()?;
+            let created = names.len() - scales.len();
+            for (i, physical) in names.into_iter().skip(scales.len()).enumerate() {
+                let name = match &channel.name {
+                    Some(name) if created == 1 => name.clone(),
+                    Some(name) => format!("{}{}", name, i),
+                    None => physical.clone(),
+                };
+                let raw = ChannelInfo {
+                    physical : physical,
+                    name : name,
+                    units : String::from(channel.kind.units()),
+                    min : range.min,
+                    max : range.max,
+                    terminal : if channel.kind.has_terminal() { Some(channel.terminal) } else { None }
+                };
+                match &channel.scale {
+                    Some(scale) => {
+                        let scaled_range = scale.range(range);
+                        channel_info.push(ChannelInfo {
+                            units : scale.units.clone(),
+                            min : scaled_range.min,
+                            max : scaled_range.max,
+                            ..raw.clone()
+                        });
+                        if scale.keep_raw {
+                            channel_info.push(ChannelInfo { name : format!("{}_raw", raw.name), ..raw });
+                        }
+                    }
+                    None => channel_info.push(raw),
+                }
+                scales.push(channel.scale.clone());
+            }
+        }
+
+        // Find number of channels created
+        let channels = backend.num_channels()?;
+        if channels == 0 {
+            return Err(Error::NoChannels);
+        }
+
+        // Set sample rate, sample count, trigger mode. Continuous acquisitions buffer several
+        // batches so that a slow consumer doesn't overflow the buffer right away
+        let buffered = match sample_mode {
+            SampleMode::Finite => sample_count,
+            SampleMode::Continuous => sample_count*BUFFER_BATCHES,
+        };
+        backend.configure_timing(sample_rate, sample_mode, buffered)?;
+
+        if let Some(trigger) = &triggers.start {
+            backend.configure_start_trigger(trigger)?;
+        }
+        if let Some(reference) = &triggers.reference {
+            backend.configure_reference_trigger(&reference.trigger, reference.pretrigger_samples)?;
+        }
+
+        if first_sample_timestamp {
+            backend.enable_first_sample_timestamp()?;
+        }
+
+        let mut samples = Vec::<f64>::new();
+        let buffer_size = (channels as usize)*(sample_count as usize);
+        samples.resize(buffer_size, 0.0);
+        let output = vec![0.0; channel_info.len()*(sample_count as usize)];
+        let columns = vec![0.0; output.len()];
+
+        // One timestamp per scan
+        let mut timestamps = Vec::<DateTime<Local>>::new();
+        timestamps.resize(sample_count as usize, Local::now());
+
+        Ok(DAQVTask {
+            backend : backend,
+            samples : samples, // data buffer
+            timestamps : timestamps,
+            sample_rate : sample_rate,
+            channels : channels.try_into().unwrap(),
+            scales : scales,
+            output : output,
+            columns : columns,
+            channel_info : channel_info,
+            sample_count : sample_count,
+            sample_mode : sample_mode,
+            samples_read : 0,
+            running : false,
+            read_since_start : 0,
+            overruns : 0,
+            samples_dropped : 0,
+            errors : Vec::new(),
+            triggered : !triggers.is_empty(),
+            awaiting_trigger : false,
+            t0_pending : false,
+            t0 : Local::now(),
+            epoch : None
+        })
+    }
+
+    /// Create the task described by the configuration, after checking it with
+    /// [`Config::validate`]
+    pub fn from_config(config : &Config) -> Result<DAQVTask, Error> {
+        config.validate().map_err(Error::InvalidConfig)?;
+        let backend = config.backend.open(&config.sim_signals)?;
+        let timing = &config.timing;
+        DAQVTask::new(backend, &config.channels, timing.rate, timing.mode, timing.size, timing.first_sample_timestamp, &config.trigger)
+    }
+
+    /// Read a batch, starting the task first unless a continuous acquisition is running
+    pub fn acquire_samples(&mut self) -> Result<Batch<'_>, DAQmxError> {
+        self.acquire_samples_until(None).map(|batch| batch.expect("reads without a stop flag wait for the trigger"))
+    }
+
+    /// Read a batch like [`acquire_samples`](DAQVTask::acquire_samples), None if `stop` is set
+    /// while waiting for the trigger
+    pub(crate) fn acquire_samples_until(&mut self, stop : Option<&AtomicUsize>) -> Result<Option<Batch<'_>>, DAQmxError> {
+        let read = match self.sample_mode {
+            SampleMode::Finite => {
+                // Start
+                self.start()?;
+                // Read
+                let read = match self.read(stop) {
+                    Ok(Some(read)) => read,
+                    Ok(None) => {
+                        self.backend.stop()?;
+                        return Ok(None);
+                    }
+                    Err(err) => {
+                        // Leave the task stopped so the next batch can start it again
+                        if let Err(err) = self.backend.stop() {
+                            self.errors.push(err);
+                        }
+                        return Err(err);
+                    }
+                };
+                // Stop
+                self.backend.stop()?;
+                read
+            }
+            SampleMode::Continuous => {
+                // Start once, then keep reading from the running task
+                if !self.running {
+                    self.start()?;
+                    self.running = true;
+                }
+                match self.read(stop) {
+                    Ok(Some(read)) => read,
+                    Ok(None) => return Ok(None),
+                    Err(err) => {
+                        if err.is_overrun() {
+                            self.recover_from_overrun();
+                        }
+                        return Err(err);
+                    }
+                }
+            }
+        };
+
+        self.finish_read(read);
+        Ok(Some(self.last_batch()))
+    }
+
+    /// Iterator reading one batch after the other, see [`Batches`] for its stop conditions
+    pub fn batches(&mut self) -> Batches<'_> {
+        Batches::new(self)
+    }
+
+    /// Samples per channel a running continuous acquisition has acquired but not yet read
+    pub fn pending(&self) -> Result<u64, DAQmxError> {
+        if !self.running {
+            return Ok(0);
+        }
+        Ok(self.backend.total_acquired()?.saturating_sub(self.read_since_start))
+    }
+
+    /// Read up to `count` samples per channel that have already been acquired, at most one batch
+    pub fn read_pending(&mut self, count : u64) -> Result<Batch<'_>, DAQmxError> {
+        let count = std::cmp::min(count, self.sample_count) as usize;
+        let read = self.backend.read(&mut self.samples[..count*self.channels], 0.0)?;
+        self.finish_read(read);
+        Ok(self.last_batch())
+    }
+
+    /// Stop a continuous acquisition
+    pub fn stop(&mut self) -> Result<(), DAQmxError> {
+        if self.running {
+            self.running = false;
+            self.backend.stop()?;
+        }
+        Ok(())
+    }
+
+    /// Read a batch into the sample buffer. Until the trigger has come, reads time out
+    /// regularly to look at `stop`, None once it is set
+    fn read(&mut self, stop : Option<&AtomicUsize>) -> Result<Option<usize>, DAQmxError> {
+        let mut read = 0;
+        while self.awaiting_trigger {
+            // The first scans after the trigger can arrive just before a read times out
+            read = self.backend.read_partial(&mut self.samples, TRIGGER_WAIT)?;
+            if read > 0 {
+                self.awaiting_trigger = false;
+            } else if stop.is_some_and(|stop| stop.load(Ordering::Relaxed) != 0) {
+                return Ok(None);
+            }
+        }
+        let rest = &mut self.samples[read*self.channels..];
+        if rest.is_empty() {
+            return Ok(Some(read));
+        }
+        Ok(Some(read + self.backend.read(rest, READ_TIMEOUT)?))
+    }
+
+    /// Timestamp and scale `read` freshly read scans
+    fn finish_read(&mut self, read : usize) {
+        // Without a device timestamp, the first sample of a triggered task is dated back from its arrival
+        if self.t0_pending {
+            let t0 = Local::now() - self.sample_offset(read as u64);
+            self.set_t0(t0);
+            self.t0_pending = false;
+        }
+
+        // Fill timestamps from the sample clock, computing each from t0 avoids accumulating rounding errors
+        for i in 0..read {
+            self.timestamps[i] = self.t0 + self.sample_offset(self.read_since_start + i as u64);
+        }
+
+        self.apply_scales(read);
+
+        self.read_since_start += read as u64;
+        self.samples_read = read;
+    }
+
+    /// Start the backend and capture the time of its first sample
+    fn start(&mut self) -> Result<(), DAQmxError> {
+        self.backend.start()?;
+        self.awaiting_trigger = self.triggered;
+        let now = Local::now();
+        match self.backend.first_sample_timestamp()? {
+            Some(timestamp) => self.set_t0(timestamp.with_timezone(&Local)),
+            None if self.triggered => self.t0_pending = true,
+            None => self.set_t0(now),
+        }
+        Ok(())
+    }
+
+    /// Take `t0` as the time of the first sample since the task was started
+    fn set_t0(&mut self, mut t0 : DateTime<Local>) {
+        // Keep timestamps monotonic across restarts even if the system clock steps back
+        if self.epoch.is_some() {
+            let earliest = self.t0 + self.sample_offset(self.read_since_start);
+            if t0 < earliest {
+                t0 = earliest;
+            }
+        }
+
+        self.t0 = t0;
+        self.epoch.get_or_insert(t0);
+        self.read_since_start = 0;
+    }
+
+    /// Time from t0 to sample `index` at the configured sample rate
+    fn sample_offset(&self, index : u64) -> TimeDelta {
+        TimeDelta::nanoseconds((index as f64*1e9/self.sample_rate).round() as i64)
+    }
+
+    /// Account for samples lost in a buffer overrun and stop the task, the next read restarts it
+    fn recover_from_overrun(&mut self) {
+        // Everything the device acquired that we didn't get to read is gone
+        match self.backend.total_acquired() {
+            Ok(acquired) => self.samples_dropped += acquired.saturating_sub(self.read_since_start),
+            Err(err) => self.errors.push(err),
+        }
+        self.overruns += 1;
+
+        if let Err(err) = self.backend.stop() {
+            self.errors.push(err);
+        }
+        self.running = false;
+    }
+
+    /// Fill the output columns of the first `scans` scans from the raw samples
+    fn apply_scales(&mut self, scans : usize) {
+        let columns = self.channel_info.len();
+        for scan in 0..scans {
+            let raw = &self.samples[scan*self.channels..(scan + 1)*self.channels];
+            let mut column = scan*columns;
+            for (value, scale) in raw.iter().zip(&self.scales) {
+                match scale {
+                    Some(scale) => {
+                        self.output[column] = scale.apply(*value);
+                        column += 1;
+                        if scale.keep_raw {
+                            self.output[column] = *value;
+                            column += 1;
+                        }
+                    }
+                    None => {
+                        self.output[column] = *value;
+                        column += 1;
+                    }
+                }
+            }
+        }
+
+        // Columns are kept a batch apart, so a short read leaves gaps at their ends
+        let stride = self.sample_count as usize;
+        for (scan, values) in self.output[..scans*columns].chunks_exact(columns).enumerate() {
+            for (column, value) in values.iter().enumerate() {
+                self.columns[column*stride + scan] = *value;
+            }
+        }
+    }
+
+    /// Scaled samples and timestamps of the last read, empty before the first one
+    pub fn last_batch(&self) -> Batch<'_> {
+        // Only part of the buffers is filled when not all samples were read
+        Batch::new(&self.channel_info, &self.timestamps[0..self.samples_read],
+            &self.output[0..self.samples_read*self.channel_info.len()], &self.columns, self.sample_count as usize)
+    }
+
+    /// Description of the acquisition for sinks, None before the first read
+    pub fn stream_info(&self) -> Option<StreamInfo> {
+        let epoch = self.epoch?;
+        Some(StreamInfo {
+            channels : self.channel_info.clone(),
+            sample_rate : self.sample_rate,
+            batch_size : self.sample_count,
+            epoch : epoch
+        })
+    }
+
+    /// Output columns, one per channel plus one per kept raw value
+    pub fn channel_info(&self) -> &[ChannelInfo] {
+        &self.channel_info
+    }
+
+    /// Samples per channel in each batch [N]
+    pub fn sample_count(&self) -> u64 {
+        self.sample_count
+    }
+
+    /// Sample rate [samples/sec]
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Buffer overruns in the continuous acquisition so far
+    pub fn overruns(&self) -> u32 {
+        self.overruns
+    }
+
+    /// Samples per channel lost to buffer overruns so far
+    pub fn samples_dropped(&self) -> u64 {
+        self.samples_dropped
+    }
+    /// Warnings the driver returned since they were last taken, the calls went on regardless
+    pub fn take_warnings(&mut self) -> Vec<DAQmxError> {
+        self.backend.take_warnings()
+    }
+
+    /// Errors since they were last taken of the calls stopping the task after a failed read,
+    /// or counting the samples a buffer overrun dropped. The read error is returned by the read
+    pub fn take_errors(&mut self) -> Vec<DAQmxError> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+    use crate::{Backend, MeasurementMode};
+    use crate::channel::parse_channel_list;
+    use crate::trigger::parse_trigger;
+
+    /// Task on a simulated 1 Hz sine without hum, sampled at 1 kHz in batches of a second
+    fn triggered_task(trigger : &str) -> DAQVTask {
+        let mut config = Config {
+            backend : Backend::Simulated,
+            channels : parse_channel_list("cDAQSimMod1/ai0").unwrap(),
+            ..Config::default()
+        };
+        config.channels[0].terminal = MeasurementMode::DIFF;
+        config.trigger.start = Some(parse_trigger(trigger).unwrap());
+        DAQVTask::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn scans_after_the_trigger_are_kept() {
+        // The trigger comes during one of the reads waiting for it, the scans that read gets
+        // are the start of the batch
+        let mut task = triggered_task("analog-edge:cDAQSimMod1/ai0:0.5");
+        let batch = task.acquire_samples().unwrap();
+        assert_eq!(batch.len(), 1000);
+        let values = batch.column(0);
+        assert!((0.5..0.51).contains(&values[0]), "batch starts at {}", values[0]);
+        for pair in values.windows(2) {
+            assert!((pair[1] - pair[0]).abs() <= 2.0*PI/1000.0 + 1e-9, "gap between {} and {}", pair[0], pair[1]);
+        }
+    }
+
+    #[test]
+    fn overruns_are_counted() {
+        let mut config = Config {
+            backend : Backend::Simulated,
+            channels : parse_channel_list("cDAQSimMod1/ai0").unwrap(),
+            ..Config::default()
+        };
+        config.timing.rate = 10000.0;
+        config.timing.size = 10;
+        config.timing.mode = SampleMode::Continuous;
+        let mut task = DAQVTask::from_config(&config).unwrap();
+        task.acquire_samples().unwrap();
+        // The input buffer holds 10 batches, 10 ms worth
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(task.acquire_samples().err().is_some_and(|err| err.is_overrun()));
+        assert_eq!(task.overruns(), 1);
+        assert!(task.samples_dropped() > 100);
+        assert!(task.take_errors().is_empty());
+        // The next read restarts the acquisition
+        assert_eq!(task.acquire_samples().unwrap().len(), 10);
+        task.stop().unwrap();
+    }
+
+    #[test]
+    fn stop_while_waiting_for_the_trigger() {
+        let mut task = triggered_task("analog-window:cDAQSimMod1/ai0:5:6");
+        let stop = AtomicUsize::new(1);
+        assert!(task.acquire_samples_until(Some(&stop)).unwrap().is_none());
+        assert!(task.stream_info().is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Hardware start and reference triggers, and software triggers evaluated on acquired samples
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::channel::parse_range;
+
+/// Direction of a signal crossing the trigger level
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edge {
+    #[default]
+    Rising,
+    Falling
+}
+
+/// When an analog window trigger fires
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowCondition {
+    /// The signal enters the window
+    #[default]
+    Entering,
+    /// The signal leaves the window
+    Leaving
+}
+
+/// Side of the level a software level trigger fires on
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Default)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LevelCondition {
+    #[default]
+    Above,
+    Below
+}
+
+/// Condition the device waits for
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Trigger {
+    /// Edge on a digital line, e.g. /cDAQ1/PFI0
+    DigitalEdge {
+        source : String,
+        #[serde(default)]
+        edge : Edge
+    },
+    /// Analog signal crossing a level, the source is a channel of the task or an analog trigger input
+    AnalogEdge {
+        source : String,
+        #[serde(default)]
+        edge : Edge,
+        level : f64
+    },
+    /// Analog signal entering or leaving the window between `bottom` and `top`
+    AnalogWindow {
+        source : String,
+        #[serde(default)]
+        when : WindowCondition,
+        bottom : f64,
+        top : f64
+    }
+}
+
+impl Trigger {
+    pub fn source(&self) -> &str {
+        match self {
+            Trigger::DigitalEdge { source, .. } | Trigger::AnalogEdge { source, .. } | Trigger::AnalogWindow { source, .. } => source,
+        }
+    }
+}
+
+/// Trigger marking the point in a finite acquisition that `pretrigger_samples` samples per
+/// channel are kept ahead of, the rest of the samples follow it
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct ReferenceTrigger {
+    #[serde(flatten)]
+    pub trigger : Trigger,
+    pub pretrigger_samples : u64
+}
+
+/// Triggers of the task, the acquisition starts immediately if there are none
+#[derive(Clone, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TriggerConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start : Option<Trigger>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reference : Option<ReferenceTrigger>
+}
+
+impl TriggerConfig {
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none() && self.reference.is_none()
+    }
+}
+
+/// Condition on the samples of one channel, evaluated in software for devices without analog
+/// triggering
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum SoftwareTrigger {
+    /// Sample at or beyond a level, fires again after the holdoff for as long as the signal stays there
+    Level {
+        /// Channel name, or physical channel of an unnamed channel
+        channel : String,
+        #[serde(default)]
+        when : LevelCondition,
+        level : f64
+    },
+    /// Signal crossing a level
+    Edge {
+        channel : String,
+        #[serde(default)]
+        edge : Edge,
+        level : f64
+    },
+    /// Signal entering or leaving the window between `bottom` and `top`
+    Window {
+        channel : String,
+        #[serde(default)]
+        when : WindowCondition,
+        bottom : f64,
+        top : f64
+    }
+}
+
+impl SoftwareTrigger {
+    pub fn channel(&self) -> &str {
+        match self {
+            SoftwareTrigger::Level { channel, .. } | SoftwareTrigger::Edge { channel, .. } | SoftwareTrigger::Window { channel, .. } => channel,
+        }
+    }
+
+    /// Whether the trigger fires on `value`, `previous` is the sample before it if there is one
+    pub fn fires(&self, previous : Option<f64>, value : f64) -> bool {
+        match *self {
+            SoftwareTrigger::Level { when : LevelCondition::Above, level, .. } => value >= level,
+            SoftwareTrigger::Level { when : LevelCondition::Below, level, .. } => value <= level,
+            SoftwareTrigger::Edge { edge, level, .. } => match (previous, edge) {
+                (Some(previous), Edge::Rising) => previous < level && value >= level,
+                (Some(previous), Edge::Falling) => previous > level && value <= level,
+                (None, _) => false,
+            },
+            SoftwareTrigger::Window { when, bottom, top, .. } => {
+                let inside = |x : f64| bottom <= x && x <= top;
+                match (previous, when) {
+                    (Some(previous), WindowCondition::Entering) => !inside(previous) && inside(value),
+                    (Some(previous), WindowCondition::Leaving) => inside(previous) && !inside(value),
+                    (None, _) => false,
+                }
+            }
+        }
+    }
+}
+
+/// Parse a trigger written as `digital-edge:SOURCE[:EDGE]`, `analog-edge:SOURCE:LEVEL[:EDGE]`
+/// or `analog-window:SOURCE:BOTTOM:TOP[:entering|leaving]`, e.g. `analog-edge:Dev1/ai0:2.5:falling`
+pub fn parse_trigger(s : &str) -> Result<Trigger, String> {
+    let fields : Vec<&str> = s.split(':').map(str::trim).collect();
+    let source = match fields.get(1) {
+        Some(source) if !source.is_empty() => source.to_string(),
+        _ => return Err(format!("trigger source missing: {}", s)),
+    };
+    let edge = |field : Option<&&str>| match field {
+        Some(edge) => Edge::from_str(edge, true).map_err(|_| format!("expected rising or falling, got {}", edge)),
+        None => Ok(Edge::Rising),
+    };
+    let number = |field : &str| field.parse::<f64>().map_err(|_| format!("invalid trigger level: {}", field));
+    match (fields[0], fields.len()) {
+        ("digital-edge", 2..=3) => Ok(Trigger::DigitalEdge { source : source, edge : edge(fields.get(2))? }),
+        ("analog-edge", 3..=4) => Ok(Trigger::AnalogEdge { source : source, edge : edge(fields.get(3))?, level : number(fields[2])? }),
+        ("analog-window", 4..=5) => {
+            let window = parse_range(&format!("{}:{}", fields[2], fields[3]))?;
+            let when = match fields.get(4) {
+                Some(when) => WindowCondition::from_str(when, true).map_err(|_| format!("expected entering or leaving, got {}", when))?,
+                None => WindowCondition::Entering,
+            };
+            Ok(Trigger::AnalogWindow { source : source, when : when, bottom : window.min, top : window.max })
+        }
+        _ => Err(format!("expected digital-edge:SOURCE[:EDGE], analog-edge:SOURCE:LEVEL[:EDGE] or analog-window:SOURCE:BOTTOM:TOP[:WHEN], got {}", s)),
+    }
+}
+
+/// Parse a software trigger written as `level:CHANNEL:LEVEL[:above|below]`,
+/// `edge:CHANNEL:LEVEL[:rising|falling]` or `window:CHANNEL:BOTTOM:TOP[:entering|leaving]`,
+/// e.g. `edge:supply:2.5:falling`
+pub fn parse_software_trigger(s : &str) -> Result<SoftwareTrigger, String> {
+    let fields : Vec<&str> = s.split(':').map(str::trim).collect();
+    let channel = match fields.get(1) {
+        Some(channel) if !channel.is_empty() => channel.to_string(),
+        _ => return Err(format!("trigger channel missing: {}", s)),
+    };
+    let number = |field : &str| field.parse::<f64>().map_err(|_| format!("invalid trigger level: {}", field));
+    match (fields[0], fields.len()) {
+        ("level", 3..=4) => {
+            let when = match fields.get(3) {
+                Some(when) => LevelCondition::from_str(when, true).map_err(|_| format!("expected above or below, got {}", when))?,
+                None => LevelCondition::Above,
+            };
+            Ok(SoftwareTrigger::Level { channel : channel, when : when, level : number(fields[2])? })
+        }
+        ("edge", 3..=4) => {
+            let edge = match fields.get(3) {
+                Some(edge) => Edge::from_str(edge, true).map_err(|_| format!("expected rising or falling, got {}", edge))?,
+                None => Edge::Rising,
+            };
+            Ok(SoftwareTrigger::Edge { channel : channel, edge : edge, level : number(fields[2])? })
+        }
+        ("window", 4..=5) => {
+            let window = parse_range(&format!("{}:{}", fields[2], fields[3]))?;
+            let when = match fields.get(4) {
+                Some(when) => WindowCondition::from_str(when, true).map_err(|_| format!("expected entering or leaving, got {}", when))?,
+                None => WindowCondition::Entering,
+            };
+            Ok(SoftwareTrigger::Window { channel : channel, when : when, bottom : window.min, top : window.max })
+        }
+        _ => Err(format!("expected level:CHANNEL:LEVEL[:WHEN], edge:CHANNEL:LEVEL[:EDGE] or window:CHANNEL:BOTTOM:TOP[:WHEN], got {}", s)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{AcquisitionBackend, SampleMode, SimulatedBackend, ERR_INVALID_ATTRIBUTE_VALUE};
+    use crate::channel::parse_channel_list;
+    use crate::{Config, MeasurementMode};
+
+    #[test]
+    fn digital_edge() {
+        assert_eq!(parse_trigger("digital-edge:/cDAQ1/PFI0"), Ok(Trigger::DigitalEdge { source : String::from("/cDAQ1/PFI0"), edge : Edge::Rising }));
+        assert_eq!(parse_trigger("digital-edge: /cDAQ1/PFI0 :Falling"), Ok(Trigger::DigitalEdge { source : String::from("/cDAQ1/PFI0"), edge : Edge::Falling }));
+        assert!(parse_trigger("digital-edge:/cDAQ1/PFI0:up").is_err());
+        assert!(parse_trigger("digital-edge:").is_err());
+        assert!(parse_trigger("digital-edge:/cDAQ1/PFI0:rising:1").is_err());
+    }
+
+    #[test]
+    fn analog_edge() {
+        assert_eq!(parse_trigger("analog-edge:Dev1/ai0:2.5"), Ok(Trigger::AnalogEdge { source : String::from("Dev1/ai0"), edge : Edge::Rising, level : 2.5 }));
+        assert_eq!(parse_trigger("analog-edge:Dev1/ai0:-1e-3:falling"), Ok(Trigger::AnalogEdge { source : String::from("Dev1/ai0"), edge : Edge::Falling, level : -1e-3 }));
+        assert!(parse_trigger("analog-edge:Dev1/ai0").is_err());
+        assert!(parse_trigger("analog-edge:Dev1/ai0:high").is_err());
+        assert!(parse_trigger("analog-level:Dev1/ai0:2.5").is_err());
+    }
+
+    #[test]
+    fn analog_window() {
+        assert_eq!(parse_trigger("analog-window:Dev1/ai0:-1:1"), Ok(Trigger::AnalogWindow { source : String::from("Dev1/ai0"), when : WindowCondition::Entering, bottom : -1.0, top : 1.0 }));
+        assert_eq!(parse_trigger("analog-window:Dev1/ai0:0.5:2:leaving"), Ok(Trigger::AnalogWindow { source : String::from("Dev1/ai0"), when : WindowCondition::Leaving, bottom : 0.5, top : 2.0 }));
+        // The bottom of the window comes first
+        assert!(parse_trigger("analog-window:Dev1/ai0:1:-1").is_err());
+        assert!(parse_trigger("analog-window:Dev1/ai0:1:1").is_err());
+        assert!(parse_trigger("analog-window:Dev1/ai0:-1:1:inside").is_err());
+    }
+
+    #[test]
+    fn software_triggers() {
+        assert_eq!(parse_software_trigger("level:supply:9.5:below"), Ok(SoftwareTrigger::Level { channel : String::from("supply"), when : LevelCondition::Below, level : 9.5 }));
+        assert_eq!(parse_software_trigger("edge:Dev1/ai0:-2.5"), Ok(SoftwareTrigger::Edge { channel : String::from("Dev1/ai0"), edge : Edge::Rising, level : -2.5 }));
+        assert!(parse_software_trigger("window:supply:2:1").is_err());
+
+        let edge = parse_software_trigger("edge:supply:1:falling").unwrap();
+        assert!(!edge.fires(None, 0.0));
+        assert!(edge.fires(Some(1.5), 1.0));
+        assert!(!edge.fires(Some(1.0), 0.5));
+        let window = parse_software_trigger("window:supply:-1:1:leaving").unwrap();
+        assert!(window.fires(Some(0.0), 1.5));
+        assert!(!window.fires(Some(2.0), 1.5));
+    }
+
+    #[test]
+    fn pretrigger_samples() {
+        let reference : ReferenceTrigger = toml::from_str("type = \"analog-edge\"\nsource = \"Dev1/ai0\"\nlevel = 0.5\npretrigger_samples = 100").unwrap();
+        assert_eq!(reference.pretrigger_samples, 100);
+        assert!(toml::from_str::<ReferenceTrigger>("type = \"analog-edge\"\nsource = \"Dev1/ai0\"\nlevel = 0.5\npretrigger_samples = -1").is_err());
+
+        // The pretrigger samples have to leave samples after the trigger
+        let mut config = Config { channels : parse_channel_list("Dev1/ai0").unwrap(), ..Config::default() };
+        config.timing.size = 100;
+        config.trigger.reference = Some(reference);
+        assert!(config.validate().is_err());
+        config.timing.size = 101;
+        assert!(config.validate().is_ok());
+        config.timing.mode = SampleMode::Continuous;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn pretrigger_samples_fit_the_driver() {
+        let trigger = parse_trigger("analog-edge:cDAQSimMod1/ai0:0.5").unwrap();
+        let mut backend = SimulatedBackend::new(&[]);
+        backend.create_voltage_channels("cDAQSimMod1/ai0", MeasurementMode::DIFF, -10.0, 10.0).unwrap();
+        backend.configure_timing(1000.0, SampleMode::Finite, 1 << 33).unwrap();
+        let err = backend.configure_reference_trigger(&trigger, 1 << 32).unwrap_err();
+        assert_eq!(err.code(), ERR_INVALID_ATTRIBUTE_VALUE);
+        assert!(backend.configure_reference_trigger(&trigger, u32::MAX as u64).is_ok());
+    }
+}